@@ -0,0 +1,70 @@
+//! Lookup speed of `Router` with a few thousand routes.
+//!
+//! Every group of routes mixes static segments, typed parameters, plain
+//! parameters and a catch-all, so the lookups walk all kinds of nodes.
+//!
+//!   $ cargo bench --bench router
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use webserver::http::METHOD;
+use webserver::response::make_text_response;
+use webserver::router::Router;
+
+
+const GROUPS: usize = 500;
+const DURATION: Duration = Duration::from_secs(2);
+
+
+fn build_router() -> Router<'static> {
+    let mut router = Router::new();
+    for g in 0..GROUPS {
+        let paths = [
+            format!("/g{g}/items"),
+            format!("/g{g}/items/{{id:int}}"),
+            format!("/g{g}/items/{{id:int}}/edit"),
+            format!("/g{g}/users/{{name}}"),
+            format!("/g{g}/codes/{{code:[A-Z]{{3}}}}"),
+            format!("/g{g}/files/{{rest:path}}"),
+        ];
+        for path in paths {
+            // routes borrow their path for the lifetime of the router
+            router.get(path.leak(), |(_request, _path_args)| {
+                Ok(Box::new(make_text_response(200, String::from("ok"))?))
+            });
+        }
+    }
+    router
+}
+
+
+fn main() {
+    let start = Instant::now();
+    let router = build_router();
+    println!("built {} routes in {:?}", GROUPS * 6, start.elapsed());
+
+    let lookups: Vec<String> = (0..GROUPS)
+        .step_by(7)
+        .flat_map(|g| [
+            format!("/g{g}/items"),
+            format!("/g{g}/items/{g}"),
+            format!("/g{g}/items/{g}/edit"),
+            format!("/g{g}/users/user{g}"),
+            format!("/g{g}/codes/ABC"),
+            format!("/g{g}/files/a/b/c{g}.txt"),
+            format!("/g{g}/missing"),
+        ])
+        .collect();
+
+    println!("{:>12} {:>14}", "lookups", "lookups/s");
+    let start = Instant::now();
+    let mut count: usize = 0;
+    while start.elapsed() < DURATION {
+        for path in lookups.iter() {
+            black_box(router.route_path(&METHOD::GET, black_box(path)));
+        }
+        count += lookups.len();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    println!("{:>12} {:>14.0}", count, count as f64 / elapsed);
+}
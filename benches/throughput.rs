@@ -0,0 +1,90 @@
+//! Throughput of `run_multithread` with different numbers of workers.
+//!
+//! Every request sleeps in the handler to simulate I/O bound work, so the
+//! requests per second should grow with `nthreads` as workers no longer wait
+//! for each other.
+//!
+//!   $ cargo bench --bench throughput
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use webserver::app::App;
+use webserver::router::Router;
+use webserver::response::make_text_response;
+use webserver::run::run_multithread;
+
+
+const HANDLER_LATENCY: Duration = Duration::from_millis(5);
+const CLIENTS: usize = 32;
+const DURATION: Duration = Duration::from_secs(2);
+
+
+fn start_server(port: usize, nthreads: usize) {
+    let mut router = Router::new();
+    router.get("/work", |(_request, _path_args)| {
+        thread::sleep(HANDLER_LATENCY);
+        Ok(Box::new(make_text_response(200, String::from("done"))?))
+    });
+    let mut app = App::new();
+    app.include_router("", Box::new(router));
+    thread::spawn(move || run_multithread(app, "127.0.0.1", port, nthreads));
+    // wait until the listener is ready
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port as u16)).is_ok() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("server on port {port} did not start");
+}
+
+
+fn request(port: usize) -> bool {
+    let mut stream = match TcpStream::connect(("127.0.0.1", port as u16)) {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+    let req = b"GET /work HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    if stream.write_all(req).is_err() {
+        return false;
+    }
+    let mut buf = vec![];
+    stream.read_to_end(&mut buf).is_ok() && buf.starts_with(b"HTTP/1.1 200")
+}
+
+
+fn measure(port: usize) -> f64 {
+    let done = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    let clients: Vec<_> = (0..CLIENTS).map(|_| {
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            while start.elapsed() < DURATION {
+                if request(port) {
+                    done.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        })
+    }).collect();
+    for client in clients {
+        client.join().unwrap();
+    }
+    done.load(Ordering::Relaxed) as f64 / start.elapsed().as_secs_f64()
+}
+
+
+fn main() {
+    let mut baseline: Option<f64> = None;
+    println!("{:>10} {:>12} {:>8}", "nthreads", "requests/s", "speedup");
+    for (i, nthreads) in [1, 2, 4, 8, 16].iter().enumerate() {
+        let port = 5300 + i;
+        start_server(port, *nthreads);
+        let rps = measure(port);
+        let speedup = rps / *baseline.get_or_insert(rps);
+        println!("{:>10} {:>12.1} {:>7.2}x", nthreads, rps, speedup);
+    }
+}
@@ -1,54 +1,382 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
 use std::net::TcpStream;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-use crate::request::Request;
-use crate::http::PROTOCOL;
-use crate::response::{make_text_response, MakeResponse};
+use crate::request::{BodyLimits, Request};
+use crate::extensions::Extensions;
+use crate::middleware::{Middleware, run_middlewares};
+use crate::http::{METHOD, PROTOCOL};
+use crate::json::JsonValue;
+use crate::openapi::OpenApi;
+use crate::response::{MakeResponse, MakeHtmlContent, Response, make_json_response};
+use crate::error::{HttpError, IntoResponse};
 use crate::router::Router;
 use crate::router::ResponseResult;
+use crate::shutdown::Shutdown;
+use crate::thread_pool::{QueuePolicy, panic_message};
+
+
+/// Persistent connection settings
+pub struct KeepAlive {
+    /// close the connection if the next request doesn't arrive in time
+    pub timeout: Duration,
+    /// maximum number of requests served on one connection
+    pub max_requests: usize,
+}
+
+impl KeepAlive {
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            max_requests: 100,
+        }
+    }
+
+    /// Decide whether the connection can be reused after this request.
+    ///   HTTP/1.1: persistent unless "Connection: close"
+    ///   HTTP/1.0: closed unless "Connection: keep-alive"
+    pub fn is_kept(&self, request: &Request, served: usize) -> bool {
+        if served >= self.max_requests {
+            return false;
+        }
+        let default = request.protocol == PROTOCOL::HTTP_1_1;
+        match request.header("Connection") {
+            Some(value) => {
+                let mut tokens = value.split(",").map(|v| v.trim());
+                if tokens.clone().any(|v| v.eq_ignore_ascii_case("close")) {
+                    false
+                } else if tokens.any(|v| v.eq_ignore_ascii_case("keep-alive")) {
+                    true
+                } else {
+                    default
+                }
+            },
+            None => default,
+        }
+    }
+
+    fn set_headers(&self, resp: &mut Box<dyn MakeResponse>, kept: bool, served: usize) {
+        let headers = resp.headers_mut();
+        if kept {
+            headers.insert(
+                String::from("Connection"),
+                (String::from("keep-alive"), vec![]),
+            );
+            headers.insert(
+                String::from("Keep-Alive"),
+                (
+                    format!(
+                        "timeout={}, max={}",
+                        self.timeout.as_secs(),
+                        self.max_requests - served,
+                    ),
+                    vec![],
+                ),
+            );
+        } else {
+            headers.insert(
+                String::from("Connection"),
+                (String::from("close"), vec![]),
+            );
+        }
+    }
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Job queue settings of the thread pool serving the app
+pub struct QueueConfig {
+    /// maximum number of connections waiting for a worker, None if unbounded
+    pub capacity: Option<usize>,
+    pub policy: QueuePolicy,
+    /// sent in "Retry-After" when a connection is rejected
+    pub retry_after: Duration,
+}
+
+impl QueueConfig {
+    pub fn new() -> Self {
+        Self {
+            capacity: None,
+            policy: QueuePolicy::Block,
+            retry_after: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Renders the errors of one status code, see `App::set_error_handler`
+pub type ErrorHandler = Box<dyn Fn(&Request, &HttpError) -> Box<dyn MakeResponse> + Send + Sync>;
 
 
 pub struct App<'a> {
     router: Router<'a>,
+    keep_alive: KeepAlive,
+    body_limits: BodyLimits,
+    shutdown: Shutdown,
+    queue: QueueConfig,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    state: Arc<Extensions>,
+    error_handlers: HashMap<usize, ErrorHandler>,
+    openapi: Option<OpenApi>,
+    /// built on the first request, when every router is included
+    openapi_doc: OnceLock<JsonValue>,
 }
 
 impl<'a> App<'a> {
     pub fn new() -> Self {
-        Self { router: Router::new() }
+        Self {
+            router: Router::new(),
+            keep_alive: KeepAlive::new(),
+            body_limits: BodyLimits::new(),
+            shutdown: Shutdown::new(Duration::from_secs(30)),
+            queue: QueueConfig::new(),
+            middlewares: vec![],
+            state: Arc::new(Extensions::new()),
+            error_handlers: HashMap::new(),
+            openapi: None,
+            openapi_doc: OnceLock::new(),
+        }
+    }
+
+    /// Share `state` with every request, handlers and middlewares read it
+    /// from `request.state.get::<T>()`. One value is kept per type; it is
+    /// shared between workers, so wrap what must change in a `Mutex` or use
+    /// atomics.
+    pub fn with_state<T>(mut self, state: T) -> Self
+    where T: Send + Sync + 'static
+    {
+        Arc::get_mut(&mut self.state)
+            .expect("state is set before the app serves requests")
+            .insert(state);
+        self
     }
 
     pub fn include_router(&mut self, prefix: &str, router: Box<Router<'a>>) {
         self.router.include_router(prefix, router);
     }
 
-    pub fn route(&self, request: &Request) -> Option<ResponseResult> {
-        self.router.route(&request.path.to_string(), request)
+    /// Wrap every request in `middleware`, including the ones answered
+    /// with 404 or 405. App middlewares are outside of the routers' ones,
+    /// the ones added first are the outer ones.
+    pub fn add_middleware<M>(&mut self, middleware: M)
+    where M: Middleware + 'static
+    {
+        self.middlewares.push(Arc::new(middleware));
+    }
+
+    /// Render the errors with `status`, e.g. a 404 page, instead of the
+    /// default JSON body. The headers of the error, e.g. "Allow" of a 405,
+    /// are added to the response unless the handler sets them. Errors of
+    /// requests which cannot be read, e.g. 400 or 413, are rendered with
+    /// `Request::unparsed()`.
+    pub fn set_error_handler<F>(&mut self, status: usize, handler: F)
+    where F: Fn(&Request, &HttpError) -> Box<dyn MakeResponse> + Send + Sync + 'static
+    {
+        self.error_handlers.insert(status, Box::new(handler));
+    }
+
+    /// Render `error` with the handler of its status if any
+    pub fn error_response(&self, request: &Request, error: HttpError) -> Box<dyn MakeResponse> {
+        if error.status >= 500 {
+            println!("{} {}: {error}", request.method, request.path);
+        }
+        match self.error_handlers.get(&error.status) {
+            Some(handler) => {
+                let mut resp = handler(request, &error);
+                for (key, value) in error.headers {
+                    resp.headers_mut().entry(key).or_insert(value);
+                }
+                resp
+            },
+            None => error.into_response(),
+        }
+    }
+
+    /// Serve the OpenAPI document of the routes at `spec.path`, and a page
+    /// browsing it at `spec.docs_path`
+    pub fn set_openapi(&mut self, spec: OpenApi) {
+        self.openapi = Some(spec);
+    }
+
+    pub fn set_keep_alive(&mut self, timeout: Duration, max_requests: usize) {
+        self.keep_alive = KeepAlive { timeout, max_requests };
+    }
+
+    /// Answer the requests whose body is larger than `max_size` bytes,
+    /// without reading it, or decodes to more than `max_decoded_size`
+    /// bytes with 413
+    pub fn set_body_limits(&mut self, limits: BodyLimits) {
+        self.body_limits = limits;
+    }
+
+    /// Bound the queue of connections waiting for a worker
+    pub fn set_queue(&mut self, capacity: Option<usize>, policy: QueuePolicy, retry_after: Duration) {
+        self.queue = QueueConfig { capacity, policy, retry_after };
+    }
+
+    pub fn queue(&self) -> &QueueConfig {
+        &self.queue
+    }
+
+    /// Set the grace period of in-flight requests when shutting down.
+    /// Handles taken before are not affected.
+    pub fn set_shutdown_timeout(&mut self, grace: Duration) {
+        self.shutdown = Shutdown::new(grace);
+    }
+
+    /// Handle to stop the server running this app
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    pub fn route(&self, request: &mut Request) -> Option<ResponseResult> {
+        let path = request.path.clone();
+        if let Some(resp) = self.route_openapi(&path, request) {
+            return Some(resp);
+        }
+        self.router.route(&path, request, &|request, e| self.error_response(request, e))
+    }
+
+    fn route_openapi(&self, path: &str, request: &Request) -> Option<ResponseResult> {
+        let spec = self.openapi.as_ref()?;
+        if request.method != METHOD::GET && request.method != METHOD::HEAD {
+            return None;
+        }
+        if path == spec.path {
+            let doc = self.openapi_doc.get_or_init(|| self.router.openapi(spec));
+            return Some(make_json_response(200, doc.clone())
+                .map(|v| -> Box<dyn MakeResponse> { Box::new(v) })
+                .map_err(HttpError::from));
+        }
+        if spec.docs_path.as_deref() == Some(path) {
+            return Some(Response::new(200, HashMap::new(), MakeHtmlContent(spec.docs_html()))
+                .map(|v| -> Box<dyn MakeResponse> { Box::new(v) })
+                .map_err(HttpError::from));
+        }
+        None
+    }
+
+    pub fn keep_alive(&self) -> &KeepAlive {
+        &self.keep_alive
     }
 
     pub fn handle_connection(&self, stream: TcpStream) -> Result<(),String> {
         println!("Connection established");
-        let request = Request::from_stream(&stream)?;
+        // the idle timeout applies while waiting for the next request
+        if let Err(e) = stream.set_read_timeout(Some(self.keep_alive.timeout)) {
+            return Err(format!("Fail to set read timeout: {e}"));
+        }
+        let _ = stream.set_nodelay(true);
+        let mut buf_reader = BufReader::new(&stream);
+        let mut served: usize = 0;
+        let guard = self.shutdown.track(&stream);
+
+        loop {
+            // don't wait for another request when shutting down
+            if served > 0 && self.shutdown.is_triggered() {
+                break;
+            }
+            // wait for the next request; EOF or timeout closes the connection
+            guard.set_idle(served > 0);
+            match buf_reader.fill_buf() {
+                Ok(buf) if !buf.is_empty() => {},
+                _ => break,
+            }
+            guard.set_idle(false);
+            served += 1;
+            if !self.handle_request(&mut buf_reader, &stream, served)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read one request from the connection and write its response.
+    /// `served` counts the requests on this connection including this one.
+    /// Returns whether the connection should be kept alive.
+    pub fn handle_request(
+        &self,
+        buf_reader: &mut BufReader<&TcpStream>,
+        stream: &TcpStream,
+        served: usize,
+    ) -> Result<bool,String> {
+        let mut request = match Request::from_reader(buf_reader, &self.body_limits) {
+            Ok(v) => v,
+            Err(e) => {
+                println!("{e}");
+                let mut request = Request::unparsed();
+                request.state = Arc::clone(&self.state);
+                let mut resp = self.error_response(&request, e);
+                self.keep_alive.set_headers(&mut resp, false, served);
+                let _ = resp.write(stream);
+                return Ok(false);
+            },
+        };
         println!("{}", request);
+        request.state = Arc::clone(&self.state);
         // TODO: validate from schema
         // TODO: handle validation error
 
-        // let args = HashMap::<String, String>::new();
-
-        let default_response = make_text_response(404, String::from("Not found"))?;
-        if request.protocol != PROTOCOL::HTTP_1_1 {
-            match default_response.write(stream) {
-                _ => (),
-            };
-        } else if let Some(resp) = self.route(&request) {
-            let resp = resp?;
-            // TODO: error to error response
-            match resp.write(stream) {
-                _ => (),
-            };
+        let mut kept = self.keep_alive.is_kept(&request, served)
+            && !self.shutdown.is_triggered();
+        let mut resp: Box<dyn MakeResponse> = if request.protocol != PROTOCOL::HTTP_1_1
+            && request.protocol != PROTOCOL::HTTP_1_0 {
+            self.error_response(&request, HttpError::new(505, "HTTP Version Not Supported"))
         } else {
-            match default_response.write(stream) {
-                _ => (),
+            let handle = |request: &mut Request| -> ResponseResult {
+                match self.route(request) {
+                    Some(resp) => resp,
+                    None => Err(HttpError::not_found()),
+                }
             };
+            // errors are rendered inside the middlewares, so their after
+            // hooks get them as responses
+            let render = |request: &Request, e| self.error_response(request, e);
+            match catch_unwind(AssertUnwindSafe(|| {
+                run_middlewares(&self.middlewares, &mut request, handle, &render)
+            })) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    println!(
+                        "Handler of {} {} panicked: {}",
+                        request.method,
+                        request.path,
+                        panic_message(&*e),
+                    );
+                    kept = false;
+                    self.error_response(&request, HttpError::internal())
+                },
+            }
+        };
+        if request.protocol == PROTOCOL::HTTP_1_0 && resp.is_chunked() {
+            // HTTP/1.0 doesn't know chunked encoding, delimit the body by
+            // closing the connection instead
+            resp.headers_mut().retain(|k, _| !k.eq_ignore_ascii_case("Transfer-Encoding"));
+            kept = false;
         }
-        Ok(())
+        self.keep_alive.set_headers(&mut resp, kept, served);
+        let written = if request.method == METHOD::HEAD {
+            resp.write_head(stream)
+        } else {
+            resp.write(stream)
+        };
+        if written.is_err() {
+            return Ok(false);
+        }
+        Ok(kept)
     }
 }
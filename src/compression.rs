@@ -0,0 +1,374 @@
+use std::io::{self, Read, Write};
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+
+use crate::conditional::header_value;
+use crate::error::HttpError;
+use crate::http;
+use crate::middleware::Middleware;
+use crate::request::Request;
+use crate::response::{
+    Body,
+    BodyChunks,
+    MakeResponse,
+    make_empty_response,
+    make_stream_response,
+};
+
+
+/// Content codings of a body other than the identity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Gzip,
+    /// the zlib format, as "deflate" is defined by HTTP
+    Deflate,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+            Some(Encoding::Gzip)
+        } else if name.eq_ignore_ascii_case("deflate") {
+            Some(Encoding::Deflate)
+        } else {
+            None
+        }
+    }
+}
+
+/// Codings of an "Accept-Encoding" value with their q-value, e.g.
+/// `gzip;q=1.0, deflate;q=0.5, *;q=0`. An invalid q-value is 0.
+fn parse_accept_encoding(value: &str) -> Vec<(&str, f32)> {
+    value.split(',')
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let coding = params.next()?.trim();
+            if coding.is_empty() {
+                return None;
+            }
+            let mut q = 1.0;
+            for param in params {
+                if let Some((key, value)) = param.split_once('=') {
+                    if key.trim().eq_ignore_ascii_case("q") {
+                        q = value.trim().parse::<f32>().unwrap_or(0.0).clamp(0.0, 1.0);
+                    }
+                }
+            }
+            Some((coding, q))
+        })
+        .collect()
+}
+
+/// Preferred coding among `available` for an "Accept-Encoding" value, the
+/// first of `available` between equal q-values. None is the identity, the
+/// body is sent as it is.
+pub fn negotiate_encoding(value: &str, available: &[Encoding]) -> Option<Encoding> {
+    let accepted = parse_accept_encoding(value);
+    let q_of = |name: &str| {
+        accepted.iter()
+            .find(|(coding, _)| match Encoding::from_name(coding) {
+                Some(encoding) => encoding.as_str() == name,
+                None => coding.eq_ignore_ascii_case(name),
+            })
+            .or_else(|| accepted.iter().find(|(coding, _)| *coding == "*"))
+            .map(|(_, q)| *q)
+    };
+    // a coding is preferred to the identity unless the identity is listed
+    // with a higher q-value
+    let identity = q_of("identity").unwrap_or(0.0);
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in available {
+        // codings not listed are not acceptable
+        let q = q_of(encoding.as_str()).unwrap_or(0.0);
+        if q > 0.0 && best.is_none_or(|(_, best)| q > best) {
+            best = Some((*encoding, q));
+        }
+    }
+    match best {
+        Some((encoding, q)) if q >= identity => Some(encoding),
+        _ => None,
+    }
+}
+
+/// Read at most `limit` bytes from a decoder
+fn read_limited<R: Read>(decoder: R, limit: usize) -> Result<Vec<u8>, HttpError> {
+    let mut out = vec![];
+    if let Err(e) = decoder.take(limit as u64 + 1).read_to_end(&mut out) {
+        return Err(HttpError::bad_request(&format!("Fail to decode request body: {e}")));
+    }
+    if out.len() > limit {
+        return Err(HttpError::new(413, &format!("Decoded request body is larger than {limit} bytes")));
+    }
+    Ok(out)
+}
+
+/// Whether `body` starts with a zlib header, RFC 1950 section 2.2
+fn is_zlib(body: &[u8]) -> bool {
+    match body {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (*cmf as u16 * 256 + *flg as u16) % 31 == 0,
+        _ => false,
+    }
+}
+
+/// Undo the codings of a "Content-Encoding" value, e.g. "gzip", from the
+/// last one applied. The error is 415 for an unknown coding, 413 for a
+/// body decoding to more than `limit` bytes and 400 for invalid data.
+pub fn decode_body(body: Vec<u8>, content_encoding: &str, limit: usize) -> Result<Vec<u8>, HttpError> {
+    let mut body = body;
+    for coding in content_encoding.rsplit(',').map(|v| v.trim()) {
+        if coding.is_empty() || coding.eq_ignore_ascii_case("identity") {
+            continue;
+        }
+        body = match Encoding::from_name(coding) {
+            Some(Encoding::Gzip) => read_limited(MultiGzDecoder::new(&body[..]), limit)?,
+            Some(Encoding::Deflate) if is_zlib(&body) => read_limited(ZlibDecoder::new(&body[..]), limit)?,
+            // some clients send raw deflate data without the zlib wrapper
+            Some(Encoding::Deflate) => read_limited(DeflateDecoder::new(&body[..]), limit)?,
+            None => {
+                return Err(HttpError::new(415, &format!("Unsupported Content-Encoding {coding}"))
+                    .with_header("Accept-Encoding", "gzip, deflate"));
+            },
+        };
+    }
+    Ok(body)
+}
+
+/// Add `name` to the "Vary" of a response, once
+pub fn add_vary(headers: &mut http::Headers, name: &str) {
+    let key = headers.keys()
+        .find(|k| k.eq_ignore_ascii_case("Vary"))
+        .cloned()
+        .unwrap_or_else(|| String::from("Vary"));
+    let (value, _) = headers.entry(key).or_insert_with(|| (String::new(), vec![]));
+    if value.split(',').any(|v| v.trim() == "*" || v.trim().eq_ignore_ascii_case(name)) {
+        return;
+    }
+    if !value.is_empty() {
+        value.push_str(", ");
+    }
+    value.push_str(name);
+}
+
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding, level: u32) -> Self {
+        let level = flate2::Compression::new(level.min(9));
+        match encoding {
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(vec![], level)),
+            Encoding::Deflate => Encoder::Deflate(ZlibEncoder::new(vec![], level)),
+        }
+    }
+
+    /// Compress `bytes` and take the output produced so far, which is
+    /// often empty for small inputs
+    fn write(&mut self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Gzip(e) => {
+                e.write_all(bytes)?;
+                Ok(std::mem::take(e.get_mut()))
+            },
+            Encoder::Deflate(e) => {
+                e.write_all(bytes)?;
+                Ok(std::mem::take(e.get_mut()))
+            },
+        }
+    }
+
+    /// The rest of the output, with the trailer of the format
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Gzip(e) => e.finish(),
+            Encoder::Deflate(e) => e.finish(),
+        }
+    }
+}
+
+/// Compress a body in memory
+pub fn compress(bytes: &[u8], encoding: Encoding, level: u32) -> io::Result<Vec<u8>> {
+    let mut encoder = Encoder::new(encoding, level);
+    let mut out = encoder.write(bytes)?;
+    out.extend(encoder.finish()?);
+    Ok(out)
+}
+
+/// Chunks of a streaming body compressed while they are produced
+struct EncodedChunks {
+    chunks: BodyChunks,
+    encoder: Option<Encoder>,
+}
+
+impl Iterator for EncodedChunks {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let encoder = self.encoder.as_mut()?;
+            match self.chunks.next() {
+                Some(Ok(bytes)) => match encoder.write(&bytes) {
+                    Ok(out) if out.is_empty() => continue,
+                    result => return Some(result),
+                },
+                Some(Err(e)) => {
+                    self.encoder = None;
+                    return Some(Err(e));
+                },
+                None => return self.encoder.take().map(|v| v.finish()),
+            }
+        }
+    }
+}
+
+
+/// Compresses the bodies of responses with the coding preferred by the
+/// "Accept-Encoding" of the request.
+///
+/// Only the bodies of `mime_types` with at least `min_size` bytes are
+/// compressed, a stream of unknown length is compressed while it is sent.
+/// Bodies already encoded and partial contents are left as they are. Add
+/// it after `Conditional`, whose ETags are then those of the bodies sent.
+pub struct Compression {
+    /// smaller bodies are sent as they are, the gain would not be worth it
+    pub min_size: usize,
+    /// compressed Content-Types, those ending with '/' match a whole
+    /// type, e.g. "text/"
+    pub mime_types: Vec<String>,
+    /// from 0, no compression, to 9, the smallest and slowest
+    pub level: u32,
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Self {
+            min_size: 1024,
+            mime_types: [
+                "text/",
+                "application/json",
+                "application/javascript",
+                "application/xml",
+                "image/svg+xml",
+            ].into_iter().map(String::from).collect(),
+            level: 6,
+        }
+    }
+
+    fn is_compressible(&self, content_type: &str) -> bool {
+        let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        self.mime_types.iter().any(|v| match v.ends_with('/') {
+            true => mime.starts_with(v.as_str()),
+            false => mime == *v,
+        })
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for Compression {
+    fn after(&self, request: &Request, resp: &mut Box<dyn MakeResponse>) {
+        let status = resp.status().code;
+        // no body, or a part of the uncompressed body
+        if status < 200 || status == 204 || status == 206 || status == 304 {
+            return;
+        }
+        let headers = resp.headers();
+        if header_value(headers, "Content-Encoding").is_some()
+            || !header_value(headers, "Content-Type").is_some_and(|v| self.is_compressible(v)) {
+            return;
+        }
+        let len = header_value(headers, "Content-Length").and_then(|v| v.trim().parse::<usize>().ok());
+        if len.is_some_and(|v| v < self.min_size) {
+            return;
+        }
+        // from here the body depends on "Accept-Encoding"
+        add_vary(resp.headers_mut(), "Accept-Encoding");
+        let encoding = request.header("Accept-Encoding")
+            .and_then(|v| negotiate_encoding(v, &[Encoding::Gzip, Encoding::Deflate]));
+        if let Some(encoding) = encoding {
+            encode_response(resp, encoding, self.level);
+        }
+    }
+}
+
+/// Replace the body of `resp` with its compression, a body in memory stays
+/// in memory and a stream is sent chunked
+fn encode_response(resp: &mut Box<dyn MakeResponse>, encoding: Encoding, level: u32) {
+    let empty = match make_empty_response(204) {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    let taken = std::mem::replace(resp, Box::new(empty));
+    let status = taken.status().code;
+    let mut headers = taken.headers().clone();
+    let (body, len) = match taken.into_body() {
+        Body::Full(bytes) => match compress(&bytes, encoding, level) {
+            Ok(v) => {
+                let len = v.len();
+                (Body::Full(v), Some(len))
+            },
+            // not reached, writing to memory does not fail
+            Err(e) => {
+                println!("Fail to compress the body: {e}");
+                let len = bytes.len();
+                set_body(resp, status, headers, Some(len), Body::Full(bytes));
+                return;
+            },
+        },
+        Body::Stream(chunks) => {
+            let chunks = EncodedChunks { chunks, encoder: Some(Encoder::new(encoding, level)) };
+            (Body::Stream(Box::new(chunks)), None)
+        },
+    };
+    // ranges are of the uncompressed body
+    headers.retain(|k, _| !k.eq_ignore_ascii_case("Accept-Ranges"));
+    headers.insert(String::from("Content-Encoding"), (String::from(encoding.as_str()), vec![]));
+    // the compressed bytes differ, a weak ETag still matches the same
+    // content in "If-None-Match"
+    if let Some(etag) = headers.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case("ETag")) {
+        if !etag.1.0.starts_with("W/") {
+            etag.1.0 = format!("W/{}", etag.1.0);
+        }
+    }
+    set_body(resp, status, headers, len, body);
+}
+
+/// Set the body of `resp` and its "Content-Length", chunked if `len` is None
+fn set_body(
+    resp: &mut Box<dyn MakeResponse>,
+    status: usize,
+    mut headers: http::Headers,
+    len: Option<usize>,
+    body: Body,
+) {
+    headers.retain(|k, _| {
+        !k.eq_ignore_ascii_case("Content-Length") && !k.eq_ignore_ascii_case("Transfer-Encoding")
+    });
+    match make_stream_response(status, "", len, body) {
+        Ok(mut v) => {
+            // Content-Length or Transfer-Encoding of the new body
+            headers.extend(v.headers().iter()
+                .filter(|(k, _)| *k != "Content-Type")
+                .map(|(k, v)| (k.clone(), v.clone())));
+            *v.headers_mut() = headers;
+            *resp = Box::new(v);
+        },
+        // not reached, the status is the one of a response
+        Err(e) => println!("Fail to compress the body: {e}"),
+    }
+}
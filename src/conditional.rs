@@ -0,0 +1,246 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{HttpError, IntoResponse};
+use crate::http::{self, METHOD, parse_http_date};
+use crate::middleware::Middleware;
+use crate::request::Request;
+use crate::response::{
+    Body,
+    MakeResponse,
+    make_empty_response,
+    make_stream_response,
+};
+
+
+/// Headers of a response kept in its 304, RFC 9110 section 15.4.5
+const NOT_MODIFIED_HEADERS: [&str; 7] = [
+    "Cache-Control",
+    "Content-Location",
+    "Date",
+    "ETag",
+    "Expires",
+    "Last-Modified",
+    "Vary",
+];
+
+
+/// 64-bit FNV-1a, stable between runs and releases unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn secs(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).map(|v| v.as_secs()).ok()
+}
+
+/// Strong ETag of a content in memory, from its hash
+pub fn make_strong_etag(bytes: &[u8]) -> String {
+    format!("\"{:x}-{:016x}\"", bytes.len(), fnv1a(bytes))
+}
+
+/// Weak ETag of a file from its modification time and size
+pub fn make_weak_etag(modified: SystemTime, len: u64) -> String {
+    format!("W/\"{:x}-{len:x}\"", secs(modified).unwrap_or(0))
+}
+
+/// Entity tags of an "If-Match" or "If-None-Match" list, e.g.
+/// `"a", W/"b"`, with their quotes and weak prefix
+fn parse_etags(value: &str) -> Vec<&str> {
+    let mut tags = vec![];
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        let start = rest;
+        if let Some(v) = rest.strip_prefix("W/") {
+            rest = v;
+        }
+        let end = match rest.strip_prefix('"').and_then(|v| v.find('"')) {
+            // opening quote, opaque-tag, closing quote
+            Some(i) => i + 2,
+            None => break,
+        };
+        rest = &rest[end..];
+        tags.push(&start[..start.len() - rest.len()]);
+    }
+    tags
+}
+
+/// Compare two entity tags, weakly ignores the weak prefix
+fn etag_eq(a: &str, b: &str, weak: bool) -> bool {
+    let a_weak = a.starts_with("W/");
+    let b_weak = b.starts_with("W/");
+    if !weak && (a_weak || b_weak) {
+        return false;
+    }
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+/// Whether an "If-Match" or "If-None-Match" value matches `etag`
+fn etag_list_matches(value: &str, etag: Option<&str>, weak: bool) -> bool {
+    // any current representation
+    if value.trim() == "*" {
+        return true;
+    }
+    match etag {
+        Some(etag) => parse_etags(value).iter().any(|v| etag_eq(v, etag, weak)),
+        None => false,
+    }
+}
+
+/// Whether the "If-Range" of the request matches the content: an entity
+/// tag is compared strongly, a date must be the modification time
+pub fn if_range_matches(request: &Request, etag: Option<&str>, modified: Option<SystemTime>) -> bool {
+    let value = match request.header("If-Range") {
+        Some(v) => v.trim(),
+        None => return true,
+    };
+    if value.starts_with('"') || value.starts_with("W/") {
+        return etag.is_some_and(|etag| etag_eq(value, etag, false));
+    }
+    match (parse_http_date(value), modified) {
+        (Some(date), Some(modified)) => secs(date) == secs(modified),
+        _ => false,
+    }
+}
+
+
+/// Result of the conditional headers of a request
+#[derive(Debug, PartialEq)]
+pub enum Precondition {
+    /// send the response
+    Proceed,
+    /// 304, the client has the current representation
+    NotModified,
+    /// 412, e.g. the representation changed since the client read it
+    Failed,
+}
+
+/// Evaluate "If-Match", "If-Unmodified-Since", "If-None-Match" and
+/// "If-Modified-Since" against the current representation, in the order
+/// of RFC 9110 section 13.2.2. Handlers changing a resource call it before
+/// changing it, to refuse to overwrite a newer version.
+pub fn evaluate_preconditions(
+    request: &Request,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> Precondition {
+    let safe = request.method == METHOD::GET || request.method == METHOD::HEAD;
+    let modified = last_modified.and_then(secs);
+    let since = |name: &str| request.header(name).and_then(|v| parse_http_date(v)).and_then(secs);
+
+    if let Some(value) = request.header("If-Match") {
+        if !etag_list_matches(value, etag, false) {
+            return Precondition::Failed;
+        }
+    } else if let (Some(date), Some(modified)) = (since("If-Unmodified-Since"), modified) {
+        if modified > date {
+            return Precondition::Failed;
+        }
+    }
+    if let Some(value) = request.header("If-None-Match") {
+        if etag_list_matches(value, etag, true) {
+            return if safe { Precondition::NotModified } else { Precondition::Failed };
+        }
+    } else if let (true, Some(date), Some(modified)) = (safe, since("If-Modified-Since"), modified) {
+        if modified <= date {
+            return Precondition::NotModified;
+        }
+    }
+    Precondition::Proceed
+}
+
+/// 304 with the validators and caching headers of `headers`
+pub fn make_not_modified_response(headers: &http::Headers) -> Result<Box<dyn MakeResponse>, String> {
+    let mut resp: Box<dyn MakeResponse> = Box::new(make_empty_response(304)?);
+    for (key, value) in headers.iter() {
+        if NOT_MODIFIED_HEADERS.iter().any(|v| v.eq_ignore_ascii_case(key)) {
+            resp.headers_mut().insert(key.clone(), value.clone());
+        }
+    }
+    Ok(resp)
+}
+
+pub(crate) fn header_value<'a>(headers: &'a http::Headers, key: &str) -> Option<&'a str> {
+    headers.iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, (v, _))| v.as_str())
+}
+
+/// Evaluate the conditional headers of `request` against the "ETag" and
+/// "Last-Modified" of a 2xx `resp`, and answer 304 or 412 instead of it
+pub fn conditional_response(request: &Request, resp: Box<dyn MakeResponse>) -> Box<dyn MakeResponse> {
+    if !(200..300).contains(&resp.status().code) {
+        return resp;
+    }
+    let headers = resp.headers();
+    let last_modified = header_value(headers, "Last-Modified").and_then(parse_http_date);
+    match evaluate_preconditions(request, header_value(headers, "ETag"), last_modified) {
+        Precondition::Proceed => resp,
+        Precondition::NotModified => match make_not_modified_response(headers) {
+            Ok(v) => v,
+            Err(_) => resp,
+        },
+        Precondition::Failed => HttpError::new(412, "Precondition Failed").into_response(),
+    }
+}
+
+
+/// Answers 304 and 412 to the conditional requests.
+///
+/// Successful responses to GET and HEAD with a body in memory get a strong
+/// "ETag" from its content unless they have one. Other methods are left to
+/// their handlers, which evaluate "If-Match" with `evaluate_preconditions`
+/// before changing anything.
+pub struct Conditional;
+
+impl Middleware for Conditional {
+    fn after(&self, request: &Request, resp: &mut Box<dyn MakeResponse>) {
+        // the change asked by other methods is already done
+        let safe = request.method == METHOD::GET || request.method == METHOD::HEAD;
+        if !safe || !(200..300).contains(&resp.status().code) {
+            return;
+        }
+        if header_value(resp.headers(), "ETag").is_none() && !resp.is_chunked() {
+            add_strong_etag(resp);
+        }
+        let empty = match make_empty_response(204) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+        let taken = std::mem::replace(resp, Box::new(empty));
+        *resp = conditional_response(request, taken);
+    }
+}
+
+/// Hash the body of `resp` if it is in memory, a stream is left as it is
+fn add_strong_etag(resp: &mut Box<dyn MakeResponse>) {
+    let empty = match make_empty_response(204) {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    let taken = std::mem::replace(resp, Box::new(empty));
+    let status = taken.status().code;
+    let mut headers = taken.headers().clone();
+    let body = taken.into_body();
+    if let Body::Full(bytes) = &body {
+        headers.insert(String::from("ETag"), (make_strong_etag(bytes), vec![]));
+    }
+    // the headers, including Content-Type and Content-Length, are kept
+    let len = match &body {
+        Body::Full(bytes) => Some(bytes.len()),
+        Body::Stream(_) => None,
+    };
+    match make_stream_response(status, "", len, body) {
+        Ok(mut v) => {
+            *v.headers_mut() = headers;
+            *resp = Box::new(v);
+        },
+        // not reached, the status is the one of a response
+        Err(e) => println!("Fail to add ETag: {e}"),
+    }
+}
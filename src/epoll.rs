@@ -0,0 +1,83 @@
+//! Thin wrapper of the Linux epoll API used by `run::run_evented`.
+//! Every registered file descriptor is also used as its event token.
+use std::io;
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+pub use libc::{EPOLLIN, EPOLLONESHOT, EPOLLRDHUP, EPOLLHUP, EPOLLERR};
+
+
+pub struct Event {
+    pub fd: RawFd,
+    pub events: u32,
+}
+
+
+pub struct Epoll {
+    fd: RawFd,
+}
+
+impl Epoll {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    fn ctl(&self, op: libc::c_int, fd: RawFd, events: i32) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: events as u32,
+            u64: fd as u64,
+        };
+        if unsafe { libc::epoll_ctl(self.fd, op, fd, &mut event) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Start watching `fd` for `events`
+    pub fn add(&self, fd: RawFd, events: i32) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_ADD, fd, events)
+    }
+
+    /// Change the watched events of `fd`, also re-arms an `EPOLLONESHOT` fd
+    pub fn modify(&self, fd: RawFd, events: i32) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_MOD, fd, events)
+    }
+
+    pub fn delete(&self, fd: RawFd) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_DEL, fd, 0)
+    }
+
+    /// Wait for ready file descriptors, at most `max_events` are returned.
+    /// An interrupted wait returns no events.
+    pub fn wait(&self, max_events: usize, timeout: Option<Duration>) -> io::Result<Vec<Event>> {
+        let mut events = vec![libc::epoll_event { events: 0, u64: 0 }; max_events];
+        let timeout = match timeout {
+            Some(v) => v.as_millis().min(libc::c_int::MAX as u128) as libc::c_int,
+            None => -1,
+        };
+        let n = unsafe {
+            libc::epoll_wait(self.fd, events.as_mut_ptr(), max_events as libc::c_int, timeout)
+        };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(vec![]);
+            }
+            return Err(err);
+        }
+        Ok(events[..n as usize].iter().map(|event| Event {
+            fd: event.u64 as RawFd,
+            events: event.events,
+        }).collect())
+    }
+}
+
+impl Drop for Epoll {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd); }
+    }
+}
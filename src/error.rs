@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use crate::http;
+use crate::json::{JsonValue, json};
+use crate::schema::ValidationErrors;
+use crate::response::{
+    MakeContent,
+    MakeResponse,
+    Response,
+    make_json_response,
+    make_text_response,
+};
+
+
+/// Error of a handler or middleware, rendered as an HTTP response.
+///
+/// The default rendering is a JSON body `{"detail": ...}` holding `details`
+/// if any, otherwise `message`. `App::set_error_handler` replaces it for a
+/// status code.
+#[derive(Debug, Clone)]
+pub struct HttpError {
+    pub status: usize,
+    pub message: String,
+    pub details: Option<JsonValue>,
+    /// added to the rendered response, e.g. "Allow" of a 405
+    pub headers: http::Headers,
+}
+
+impl HttpError {
+    pub fn new(status: usize, message: &str) -> Self {
+        Self {
+            status,
+            message: String::from(message),
+            details: None,
+            headers: HashMap::new(),
+        }
+    }
+
+    pub fn with_details(mut self, details: JsonValue) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn with_header(mut self, key: &str, value: &str) -> Self {
+        self.headers.insert(String::from(key), (String::from(value), vec![]));
+        self
+    }
+
+    pub fn bad_request(message: &str) -> Self {
+        Self::new(400, message)
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(404, "Not Found")
+    }
+
+    pub fn internal() -> Self {
+        Self::new(500, "Internal Server Error")
+    }
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.status, self.message)
+    }
+}
+
+/// Plain string errors, e.g. from building a response, are internal errors.
+/// The string is logged but not sent to the client.
+impl From<String> for HttpError {
+    fn from(e: String) -> Self {
+        println!("Internal error: {e}");
+        Self::internal()
+    }
+}
+
+impl From<&str> for HttpError {
+    fn from(e: &str) -> Self {
+        Self::from(String::from(e))
+    }
+}
+
+/// Failed extraction of request data, e.g. with `parse_request(..)?`, is
+/// answered with 422 and the errors as a FastAPI style `detail` list:
+///   {"detail": [{"loc": ["query", "page"], "msg": "..."}]}
+impl From<ValidationErrors> for HttpError {
+    fn from(errs: ValidationErrors) -> Self {
+        let details: Vec<JsonValue> = errs.iter().map(|e| e.to_json()).collect();
+        Self::new(422, "Unprocessable Content").with_details(JsonValue::Array(details))
+    }
+}
+
+
+/// Conversion into a response ready to be written
+pub trait IntoResponse {
+    fn into_response(self) -> Box<dyn MakeResponse>;
+}
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Box<dyn MakeResponse> {
+        let detail = match self.details {
+            Some(details) => details,
+            None => JsonValue::String(self.message),
+        };
+        let mut resp: Box<dyn MakeResponse> = match make_json_response(self.status, json!({"detail": detail})) {
+            Ok(v) => Box::new(v),
+            // unknown status code
+            Err(_) => Box::new(make_text_response(500, String::from("Internal Server Error")).unwrap()),
+        };
+        resp.headers_mut().extend(self.headers);
+        resp
+    }
+}
+
+impl IntoResponse for Box<dyn MakeResponse> {
+    fn into_response(self) -> Box<dyn MakeResponse> {
+        self
+    }
+}
+
+impl<T: MakeContent + 'static> IntoResponse for Response<T> {
+    fn into_response(self) -> Box<dyn MakeResponse> {
+        Box::new(self)
+    }
+}
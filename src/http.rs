@@ -9,3 +9,9 @@ pub use status::{Status, STATUS, get_status_from_code};
 
 mod messege;
 pub use messege::*;
+
+mod date;
+pub use date::{format_http_date, parse_http_date};
+
+mod range;
+pub use range::{ByteRanges, MAX_RANGES, parse_range};
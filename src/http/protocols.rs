@@ -18,9 +18,9 @@ impl<'a> std::cmp::PartialEq for Protocol<'a> { fn eq(&self, other: &Self) -> bo
 }
 
 pub mod PROTOCOL {
-    pub const HTTP_1_0: super::Protocol<'_> = super::Protocol { protocol: "HTTP", version: "1" };
+    pub const HTTP_1_0: super::Protocol<'_> = super::Protocol { protocol: "HTTP", version: "1.0" };
     pub const HTTP_1_1: super::Protocol<'_> = super::Protocol { protocol: "HTTP", version: "1.1" };
-    pub const HTTP_2_0: super::Protocol<'_> = super::Protocol { protocol: "HTTP", version: "1" };
+    pub const HTTP_2_0: super::Protocol<'_> = super::Protocol { protocol: "HTTP", version: "2" };
 }
 
 pub fn get_protocol_from_str(protocol_str: &str) -> Result<Protocol<'static>, String> {
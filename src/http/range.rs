@@ -0,0 +1,84 @@
+/// Most ranges served for one request, more are answered with the whole
+/// content instead of many small parts
+pub const MAX_RANGES: usize = 16;
+
+
+/// Parts of a content of known length asked with a "Range" header
+#[derive(Debug, PartialEq)]
+pub enum ByteRanges {
+    /// no usable "Range", send the whole content
+    Full,
+    /// first and last byte of each part, sorted and without overlaps
+    Partial(Vec<(u64, u64)>),
+    /// none of the ranges is in the content, answered with 416
+    Unsatisfiable,
+}
+
+/// Parse the value of a "Range" header for a content of `len` bytes, e.g.
+///   bytes=0-499        the first 500 bytes
+///   bytes=500-         from the 501st byte to the end
+///   bytes=-500         the last 500 bytes
+///   bytes=0-0,-1       the first and the last byte
+/// Invalid values and other units are ignored like a missing header.
+pub fn parse_range(value: &str, len: u64) -> ByteRanges {
+    let specs = match value.trim().split_once('=') {
+        Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+        _ => return ByteRanges::Full,
+    };
+    let specs: Vec<&str> = specs.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()).collect();
+    if specs.is_empty() {
+        return ByteRanges::Full;
+    }
+    let mut ranges: Vec<(u64, u64)> = vec![];
+    for spec in specs {
+        let (first, last) = match spec.split_once('-') {
+            Some(v) => v,
+            None => return ByteRanges::Full,
+        };
+        let range = match (first.trim(), last.trim()) {
+            ("", "") => return ByteRanges::Full,
+            // suffix
+            ("", n) => match n.parse::<u64>() {
+                Ok(0) => None,
+                Ok(n) if len > 0 => Some((len.saturating_sub(n), len - 1)),
+                Ok(_) => None,
+                Err(_) => return ByteRanges::Full,
+            },
+            (first, last) => {
+                let first = match first.parse::<u64>() {
+                    Ok(v) => v,
+                    Err(_) => return ByteRanges::Full,
+                };
+                let last = match last {
+                    "" => u64::MAX,
+                    v => match v.parse::<u64>() {
+                        Ok(v) if v >= first => v,
+                        _ => return ByteRanges::Full,
+                    },
+                };
+                if first < len {
+                    Some((first, last.min(len - 1)))
+                } else {
+                    None
+                }
+            },
+        };
+        ranges.extend(range);
+    }
+    if ranges.is_empty() {
+        return ByteRanges::Unsatisfiable;
+    }
+    // merge the overlapping and adjacent ranges
+    ranges.sort();
+    let mut merged: Vec<(u64, u64)> = vec![];
+    for (first, last) in ranges {
+        match merged.last_mut() {
+            Some(prev) if first <= prev.1.saturating_add(1) => prev.1 = prev.1.max(last),
+            _ => merged.push((first, last)),
+        }
+    }
+    if merged.len() > MAX_RANGES {
+        return ByteRanges::Full;
+    }
+    ByteRanges::Partial(merged)
+}
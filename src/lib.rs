@@ -1,10 +1,22 @@
+// lets the code generated by webserver-derive refer to `::webserver` here too
+extern crate self as webserver;
+
 pub mod http;
 pub mod json;
 pub mod request;
+pub mod extensions;
 pub mod middleware;
 pub mod response;
+pub mod error;
 pub mod schema;
 pub mod thread_pool;
 pub mod router;
+pub mod openapi;
+pub mod static_files;
+pub mod conditional;
+pub mod compression;
 pub mod app;
 pub mod run;
+pub mod shutdown;
+#[cfg(target_os = "linux")]
+pub mod epoll;
@@ -1,18 +1,32 @@
 use std;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use num_cpus;
 
 use clap::Parser;
 
 use webserver::response::{
+    MakeResponse,
     make_text_response,
+    make_stream_response,
+    Body,
     Template,
 };
 use webserver::{json, http};
 use webserver::app::App;
-use webserver::router::Router;
-use webserver::middleware::parse_request;
-use webserver::run::run_multithread;
+use webserver::error::IntoResponse;
+use webserver::request::Request;
+use webserver::request::content_type::FileCursor;
+use webserver::router::{Router, ResponseResult};
+use webserver::openapi::OpenApi;
+use webserver::static_files::StaticFiles;
+use webserver::compression::Compression;
+use webserver::conditional::Conditional;
+use webserver::middleware::{parse_request, Middleware, RequestModel};
+use webserver::run::{run_multithread, run_evented};
+use webserver::shutdown::trigger_on_signals;
+use webserver::thread_pool::QueuePolicy;
 use webserver::schema::{Common, AnyJson, Location, HasDefault, FieldValidate};
 
 
@@ -33,79 +47,258 @@ struct ArgumentParser {
     /// number of threads
     #[arg(short, long, default_value_t = num_cpus::get())]
     nthreads: usize,
+
+    /// seconds to keep an idle connection open
+    #[arg(long, default_value_t = 5)]
+    keep_alive_timeout: u64,
+
+    /// maximum number of requests served on one connection
+    #[arg(long, default_value_t = 100)]
+    keep_alive_max: usize,
+
+    /// watch idle connections with an event loop instead of holding a thread each
+    #[arg(long, default_value_t = false)]
+    evented: bool,
+
+    /// seconds given to in-flight requests when shutting down
+    #[arg(long, default_value_t = 30)]
+    shutdown_timeout: u64,
+
+    /// maximum number of connections waiting for a worker, 0 for unbounded
+    #[arg(long, default_value_t = 0)]
+    queue_size: usize,
+
+    /// when the queue is full: block, reject or drop-oldest
+    #[arg(long, default_value = "block")]
+    queue_policy: QueuePolicy,
+
+    /// seconds sent in "Retry-After" with a rejected connection
+    #[arg(long, default_value_t = 1)]
+    retry_after: u64,
 }
 
 
 fn get_ui_router() -> Router<'static> {
     let mut router = Router::new();
+    // pages, images, fonts, ... sent as they are
+    let mut files = StaticFiles::new("templates").unwrap();
+    files.listing = true;
+    files.precompressed = true;
+    router.mount("", files);
+    router
+}
 
-    router.get("/favicon.ico", |(request, path_args)| {
-        Ok(Box::new(make_text_response(404, String::from("NOT FOUND"))?))
-    });
+/// Add the "Server" header to every response
+struct ServerHeader;
 
-    router.get("/{file_name}", |(request, path_args)| {
-        let args = HashMap::<String, String>::new();
-        println!("{request}");
-        println!("{path_args:?}");
-        println!("{:?}", request.query);
-        
-        let fname = &path_args["file_name"];
-        let headers = http::Headers::new();
-        if let Ok(resp) = TEMPLATE.make_response(200, &fname, &args, headers.clone()) {
-            Ok(Box::new(resp))
-        } else if let Ok(resp) = TEMPLATE.make_response(400, "404.html", &args, headers.clone()) {
-            Ok(Box::new(resp))
-        } else {
-            Ok(Box::new(make_text_response(404, String::from("NOT FOUND"))?))
+impl Middleware for ServerHeader {
+    fn after(&self, _request: &Request, response: &mut Box<dyn MakeResponse>) {
+        response.headers_mut().insert(
+            String::from("Server"),
+            (String::from("webserver"), vec![]),
+        );
+    }
+}
+
+/// Report the handling time in "X-Response-Time", the start time is passed
+/// from `before` to `after` in the request extensions
+struct Timing;
+
+struct StartTime(Instant);
+
+impl Middleware for Timing {
+    fn before(&self, request: &mut Request) -> Option<ResponseResult> {
+        request.extensions.insert(StartTime(Instant::now()));
+        if let Some(stats) = request.state.get::<Stats>() {
+            stats.requests.fetch_add(1, Ordering::Relaxed);
         }
-    });
-    router
+        None
+    }
+
+    fn after(&self, request: &Request, response: &mut Box<dyn MakeResponse>) {
+        if let Some(StartTime(start)) = request.extensions.get::<StartTime>() {
+            response.headers_mut().insert(
+                String::from("X-Response-Time"),
+                (format!("{}us", start.elapsed().as_micros()), vec![]),
+            );
+        }
+    }
+}
+
+/// App state shared by all requests
+struct Stats {
+    requests: AtomicUsize,
+}
+
+/// Keep API responses out of caches
+struct NoStore;
+
+impl Middleware for NoStore {
+    fn after(&self, _request: &Request, response: &mut Box<dyn MakeResponse>) {
+        response.headers_mut().insert(
+            String::from("Cache-Control"),
+            (String::from("no-store"), vec![]),
+        );
+    }
+}
+
+#[derive(RequestModel)]
+struct ListItems {
+    #[param(path)]
+    user_id: u64,
+    #[param(query, ge = 1, le = 100, default = 20)]
+    limit: i64,
+    #[param(query, max_len = 32, pattern = "^[a-z]+$")]
+    tag: Option<String>,
+    #[param(query, alias = "sort-by", choice = ["name", "date"])]
+    sort_by: Option<String>,
+    #[param(header, min_len = 8)]
+    x_api_key: String,
+    #[param(cookie)]
+    session: Option<String>,
+}
+
+#[derive(RequestModel)]
+struct NewPost {
+    #[param(form, min_len = 1, max_len = 100)]
+    title: String,
+    #[param(form, default = [])]
+    tags: Vec<String>,
+    #[param(form, allowed_exts = ["png", "jpg", "txt"])]
+    attachment: Option<FileCursor>,
+}
+
+/// Any JSON body
+fn user_parser() -> AnyJson {
+    AnyJson {
+        common: Common {
+            required: true,
+            location: Location::Body,
+            default: None,
+            field: None,
+            val: None,
+        },
+        schema: None,
+    }
 }
 
 fn get_api_router() -> Router<'static> {
     let mut router = Router::new();
+    router.add_middleware(NoStore);
 
     router.post("/user", |(request, path_args)| {
         // read json
         println!("call user");
         println!("{request}");
-        let parser: AnyJson = AnyJson {
-            common: Common {
-                required: true,
-                location: Location::Body,
-                default: None,
-                field: None,
-                val: None,
-            },
-            schema: None,
-        };
-        match parse_request(parser.clone(), &request, &path_args) {
-            Ok(Some(v)) => {
+        let parser = user_parser();
+        // invalid input is answered with 422 and the errors
+        match parse_request(parser.clone(), &request, &path_args)? {
+            Some(v) => {
                 Ok(Box::new(make_text_response(200, json::dump(&v)?)?))
             },
-            Ok(None) => {
+            None => {
                 Ok(Box::new(make_text_response(404, String::from("NOT FOUND"))?))
             }
-            Err(errs) => {
-                for err in errs {
-                    println!("{:?}", err);
-                }
-                Ok(Box::new(make_text_response(406, String::from("Bad Request"))?))
-            }
         }
-    });
+    })
+    .summary("Echo a JSON body")
+    .param(&user_parser());
+
+    router.get("/users/{user_id:int}/items", |(request, path_args)| {
+        // every invalid parameter is reported in one 422
+        let params = ListItems::from_request(request, &path_args)?;
+        let body = json::json!({
+            "user_id": params.user_id,
+            "limit": params.limit,
+            "tag": params.tag,
+            "sort_by": params.sort_by,
+            "session": params.session,
+        });
+        println!("api key: {}", params.x_api_key);
+        Ok(Box::new(make_text_response(200, json::dump(&body)?)?))
+    })
+    .summary("List the items of a user")
+    .model::<ListItems>()
+    .response(200, "The parameters read");
+
+    router.post("/posts", |(request, path_args)| {
+        // multipart or urlencoded form, validated entry by entry
+        let post = NewPost::from_request(request, &path_args)?;
+        let body = json::json!({
+            "title": post.title,
+            "tags": post.tags,
+            "attachment": post.attachment.map(|f| f.filename()),
+        });
+        Ok(Box::new(make_text_response(201, json::dump(&body)?)?))
+    })
+    .summary("Create a post")
+    .model::<NewPost>()
+    .response(201, "The created post");
+
+    router.get("/stats", |(request, _path_args)| {
+        let requests = match request.state.get::<Stats>() {
+            Some(stats) => stats.requests.load(Ordering::Relaxed),
+            None => 0,
+        };
+        Ok(Box::new(make_text_response(200, format!("requests: {requests}"))?))
+    })
+    .summary("Number of served requests")
+    .tag("stats");
+
+    router.get("/export", |(_request, _path_args)| {
+        // generate rows while they are sent instead of building the whole file
+        let rows = (0..100_000).map(|i| format!("{i},user{i}\n").into_bytes());
+        let body = Body::from_chunks(std::iter::once(b"id,name\n".to_vec()).chain(rows));
+        Ok(Box::new(make_stream_response(200, "text/csv", None, body)?))
+    })
+    .summary("Export the users as CSV")
+    .response(200, "CSV file, sent in chunks");
     router
 }
 
 fn main() {
     let args = ArgumentParser::parse();
-    let mut app: App = App::new();
+    let mut app: App = App::new().with_state(Stats { requests: AtomicUsize::new(0) });
+    app.set_keep_alive(
+        Duration::from_secs(args.keep_alive_timeout),
+        args.keep_alive_max,
+    );
+    app.set_queue(
+        if args.queue_size > 0 { Some(args.queue_size) } else { None },
+        args.queue_policy,
+        Duration::from_secs(args.retry_after),
+    );
+    app.set_shutdown_timeout(Duration::from_secs(args.shutdown_timeout));
+    // stop gracefully on SIGINT/SIGTERM
+    trigger_on_signals(app.shutdown_handle());
 
+    // not found pages are rendered from the template, API errors stay JSON
+    app.set_error_handler(404, |request, error| {
+        if request.path.starts_with("/api") {
+            return error.clone().into_response();
+        }
+        match TEMPLATE.make_response(404, "404.html", &HashMap::new(), http::Headers::new()) {
+            Ok(resp) => Box::new(resp),
+            Err(_) => error.clone().into_response(),
+        }
+    });
+    app.add_middleware(ServerHeader);
+    app.add_middleware(Timing);
+    // 304 for unchanged pages and files
+    app.add_middleware(Conditional);
+    // gzip or deflate for the clients which accept it
+    app.add_middleware(Compression::new());
     app.include_router("", Box::new(get_ui_router()));
     app.include_router("/api", Box::new(get_api_router()));
+    app.set_openapi(OpenApi::new("webserver demo", env!("CARGO_PKG_VERSION")));
 
-    match run_multithread(app, &args.ip, args.port, args.nthreads) {
-        Ok(_) => {},
+    let result = if args.evented {
+        run_evented(app, &args.ip, args.port, args.nthreads)
+    } else {
+        run_multithread(app, &args.ip, args.port, args.nthreads)
+    };
+    match result {
+        Ok(_) => println!("Server stopped"),
         Err(e) => println!("{e}"),
     }
 }
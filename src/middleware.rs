@@ -1,9 +1,14 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::request::{
     Request,
     content_type::RawDataType,
 };
+use crate::error::HttpError;
+use crate::response::MakeResponse;
+use crate::router::ResponseResult;
+use crate::openapi::Parameter;
 use crate::schema::{
     Location,
     ValidationResult,
@@ -11,6 +16,110 @@ use crate::schema::{
     FieldValidate,
 };
 
+pub use webserver_derive::RequestModel;
+
+
+/// Hooks wrapped around the handlers of an `App` or a `Router`.
+///
+/// Middlewares run in onion order: the before hooks from the outermost
+/// (the app's, then the routers' from the outer to the inner one) to the
+/// innermost, the handler, then the after hooks in reverse order.
+pub trait Middleware: Send + Sync {
+    /// Called before the handler, the request can be modified. Returning a
+    /// response skips the inner middlewares and the handler, only the after
+    /// hooks of the outer middlewares are run on it.
+    fn before(&self, _request: &mut Request) -> Option<ResponseResult> {
+        None
+    }
+
+    /// Called on the response on its way out
+    fn after(&self, _request: &Request, _response: &mut Box<dyn MakeResponse>) {
+    }
+}
+
+/// Renders an error as a response, e.g. with the error pages of the app
+pub type RenderError<'r> = dyn Fn(&Request, HttpError) -> Box<dyn MakeResponse> + 'r;
+
+/// Run `handler` wrapped by `middlewares`, the first one is the outermost.
+/// Errors of the handler and of the before hooks are rendered with
+/// `render`, so the after hooks of the outer middlewares get them as
+/// responses too.
+pub fn run_middlewares<F>(
+    middlewares: &[Arc<dyn Middleware>],
+    request: &mut Request,
+    handler: F,
+    render: &RenderError,
+) -> Box<dyn MakeResponse>
+where F: FnOnce(&mut Request) -> ResponseResult
+{
+    for (i, middleware) in middlewares.iter().enumerate() {
+        if let Some(resp) = middleware.before(request) {
+            let mut resp = resp.unwrap_or_else(|e| render(request, e));
+            for outer in middlewares[..i].iter().rev() {
+                outer.after(request, &mut resp);
+            }
+            return resp;
+        }
+    }
+    let mut resp = handler(request).unwrap_or_else(|e| render(request, e));
+    for middleware in middlewares.iter().rev() {
+        middleware.after(request, &mut resp);
+    }
+    resp
+}
+
+
+/// Request data extracted and validated in one call, usually derived with
+/// `#[derive(RequestModel)]` and `#[param(...)]` on the fields.
+pub trait RequestModel: Sized {
+    /// Read every field, the errors of all fields are returned together
+    fn from_request(
+        request: &Request,
+        path_args: &HashMap<String,String>,
+    ) -> ValidationResult<Self>;
+
+    /// Description of the fields for the OpenAPI document
+    fn parameters() -> Vec<Parameter> {
+        vec![]
+    }
+}
+
+
+/// Name the field of the errors of a validator, which doesn't know it
+fn with_field(errs: Vec<ValidationError>, name: &str) -> Vec<ValidationError> {
+    errs.into_iter()
+        .map(|mut e| {
+            if e.field.is_none() && !name.is_empty() {
+                e.field = Some(name.to_string());
+            }
+            e
+        })
+        .collect()
+}
+
+
+/// The default of a field which is not in the request, or an error if it
+/// is required
+fn missing_or_default<T>(
+    field: &T,
+    location: &Location,
+    name: &str,
+) -> ValidationResult<Option<T::Type>>
+where T: FieldValidate
+{
+    if field.required() && field.default().is_none() {
+        return Err(vec![ValidationError {
+            location: location.clone(),
+            field: Some(name.to_string()),
+            reason: format!(
+                "Missing field \"{name}\" in {}",
+                location.to_string(),
+            ),
+        }]);
+    }
+    Ok(field.default().clone())
+}
+
 
 pub fn parse_request<T>(
     field: T,
@@ -23,46 +132,51 @@ where T: FieldValidate
     let name = field.field().unwrap_or("".to_string()).clone();
     // find variable from location
     match location {
-        Location::Query | Location::Path => {
-            let src = match location {
-                Location::Query => &request.query,
-                _ => &path_args,
+        Location::Query | Location::Path | Location::Header | Location::Cookie => {
+            let value = match location {
+                Location::Query => request.query.get(&name).cloned(),
+                Location::Path => path_args.get(&name).cloned(),
+                Location::Header => request.header(&name).cloned(),
+                _ => request.cookie(&name),
             };
-            if let Some(s) = src.get(&name) {
+            if let Some(s) = &value {
                 match field.validate(RawDataType::Text(s)) {
                     Ok(v) => return Ok(Some(v)),
-                    Err(_errs) => return Err(_errs),
+                    Err(_errs) => return Err(with_field(_errs, &name)),
                 }
-            } else if field.required() && field.default().is_none() {
-                return Err(vec![ValidationError {
-                    location: location.clone(),
+            } else {
+                return missing_or_default(&field, &location, &name);
+            }
+        },
+        Location::Form => {
+            let form = match request.body.as_ref().map(|body| body.content()) {
+                Some(RawDataType::Multiple(form)) => form,
+                // only a required field is an error, the others take their
+                // default as when the form has no such entry
+                Some(_) if field.required() && field.default().is_none() => return Err(vec![ValidationError {
+                    location: Location::Form,
                     field: Some(name.to_string()),
-                    reason: format!(
-                        "Missing field \"{name}\" in {}",
-                        location.to_string(),
-                    ),
-                }])
+                    reason: String::from("Request body is not a form"),
+                }]),
+                _ => return missing_or_default(&field, &location, &name),
+            };
+            if let Some(entries) = form.get(&name).filter(|v| !v.is_empty()) {
+                let values = entries.iter().map(|v| v.content()).collect();
+                return field.validate_all(values)
+                    .map(Some)
+                    .map_err(|errs| with_field(errs, &name));
             } else {
-                match field.default() {
-                    Some(default) => return Ok(Some(default.clone())),
-                    None => return Ok(None),
-                }
+                return missing_or_default(&field, &location, &name);
             }
         },
         Location::Body => {
             if let Some(body) = &request.body {
                 match field.validate(body.content()) {
                     Ok(v) => return Ok(Some(v)),
-                    Err(_errs) => return Err(_errs),
+                    Err(_errs) => return Err(with_field(_errs, &name)),
                 }
             } else {
-                return Err(vec![ValidationError {
-                    location: Location::None,
-                    field: Some(name.to_string()),
-                    reason: format!(
-                        "Request body is empty",
-                    ),
-                }]);
+                return missing_or_default(&field, &location, &name);
             }
         },
         _ => {
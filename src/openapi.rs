@@ -0,0 +1,310 @@
+use crate::json::{JsonValue, json};
+use crate::middleware::RequestModel;
+use crate::schema::{FieldValidate, Location};
+
+
+/// A parameter of an operation, or a field of its form or JSON body
+#[derive(Clone, Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub location: Location,
+    pub required: bool,
+    pub schema: JsonValue,
+    pub description: Option<String>,
+}
+
+impl Parameter {
+    /// Describe what `field` reads from the request
+    pub fn from_field<F: FieldValidate>(field: &F) -> Self {
+        Self {
+            name: field.field().unwrap_or_default(),
+            location: field.location(),
+            required: field.required() && field.default().is_none(),
+            schema: field.json_schema(),
+            description: None,
+        }
+    }
+
+    fn to_json(&self) -> JsonValue {
+        let mut value = json!({
+            "name": self.name,
+            "in": self.location.to_string(),
+            // path parameters are always required
+            "required": self.required || matches!(self.location, Location::Path),
+            "schema": self.schema,
+        });
+        if let Some(description) = &self.description {
+            value["description"] = json!(description);
+        }
+        value
+    }
+}
+
+
+/// A declared response of an operation
+#[derive(Clone, Debug)]
+pub struct ResponseDoc {
+    pub status: usize,
+    pub description: String,
+    /// JSON schema of an "application/json" body
+    pub schema: Option<JsonValue>,
+}
+
+
+/// Documentation of a route, returned by `Router::get` and the other
+/// methods registering a route:
+///
+///   router.get("/users/{user_id:int}/items", handler)
+///       .summary("List the items of a user")
+///       .model::<ListItems>()
+///       .json_response(200, "The items", json!({"type": "array"}));
+#[derive(Clone, Debug, Default)]
+pub struct Operation {
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub parameters: Vec<Parameter>,
+    /// content type and schema of the body, overriding the one described
+    /// by the body and form parameters
+    pub request_body: Option<(String, JsonValue)>,
+    pub responses: Vec<ResponseDoc>,
+    /// left out of the document
+    pub hidden: bool,
+}
+
+impl Operation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn summary(&mut self, summary: &str) -> &mut Self {
+        self.summary = Some(String::from(summary));
+        self
+    }
+
+    pub fn description(&mut self, description: &str) -> &mut Self {
+        self.description = Some(String::from(description));
+        self
+    }
+
+    pub fn tag(&mut self, tag: &str) -> &mut Self {
+        self.tags.push(String::from(tag));
+        self
+    }
+
+    pub fn hidden(&mut self) -> &mut Self {
+        self.hidden = true;
+        self
+    }
+
+    /// Describe a field read with `parse_request`. Path parameters are
+    /// described from their converter unless given here.
+    pub fn param<F: FieldValidate>(&mut self, field: &F) -> &mut Self {
+        self.parameters.push(Parameter::from_field(field));
+        self
+    }
+
+    /// Describe the fields of a `RequestModel`
+    pub fn model<M: RequestModel>(&mut self) -> &mut Self {
+        self.parameters.extend(M::parameters());
+        self
+    }
+
+    pub fn body(&mut self, content_type: &str, schema: JsonValue) -> &mut Self {
+        self.request_body = Some((String::from(content_type), schema));
+        self
+    }
+
+    pub fn response(&mut self, status: usize, description: &str) -> &mut Self {
+        self.responses.push(ResponseDoc {
+            status,
+            description: String::from(description),
+            schema: None,
+        });
+        self
+    }
+
+    pub fn json_response(&mut self, status: usize, description: &str, schema: JsonValue) -> &mut Self {
+        self.responses.push(ResponseDoc {
+            status,
+            description: String::from(description),
+            schema: Some(schema),
+        });
+        self
+    }
+
+    /// OpenAPI operation object, `path_params` are the parameters of the
+    /// route path which are not declared
+    pub fn to_json(&self, path_params: Vec<Parameter>) -> JsonValue {
+        let mut parameters: Vec<JsonValue> = path_params.iter()
+            .filter(|p| !self.parameters.iter().any(|v| {
+                matches!(v.location, Location::Path) && v.name == p.name
+            }))
+            .map(|p| p.to_json())
+            .collect();
+        let mut form: Vec<&Parameter> = vec![];
+        let mut body: Option<&Parameter> = None;
+        for param in self.parameters.iter() {
+            match param.location {
+                Location::Path | Location::Query | Location::Header | Location::Cookie => {
+                    parameters.push(param.to_json());
+                },
+                Location::Form => form.push(param),
+                _ => body = Some(param),
+            }
+        }
+
+        let mut value = json!({
+            "parameters": parameters,
+            // the converters of the path answer 404, not 422
+            "responses": self.responses_json(!self.parameters.is_empty()),
+        });
+        if let Some(summary) = &self.summary {
+            value["summary"] = json!(summary);
+        }
+        if let Some(description) = &self.description {
+            value["description"] = json!(description);
+        }
+        if !self.tags.is_empty() {
+            value["tags"] = json!(self.tags);
+        }
+        let request_body = match (&self.request_body, body) {
+            (Some((content_type, schema)), _) => Some(json!({
+                "content": {content_type.as_str(): {"schema": schema}},
+            })),
+            (None, Some(body)) => Some(json!({
+                "required": body.required,
+                "content": {"application/json": {"schema": body.schema}},
+            })),
+            (None, None) if !form.is_empty() => {
+                let mut properties = serde_json::Map::new();
+                for param in form.iter() {
+                    properties.insert(param.name.clone(), param.schema.clone());
+                }
+                let required: Vec<&String> = form.iter()
+                    .filter(|p| p.required)
+                    .map(|p| &p.name)
+                    .collect();
+                Some(json!({
+                    "required": !required.is_empty(),
+                    "content": {"multipart/form-data": {"schema": {
+                        "type": "object",
+                        "properties": properties,
+                        "required": required,
+                    }}},
+                }))
+            },
+            (None, None) => None,
+        };
+        if let Some(request_body) = request_body {
+            value["requestBody"] = request_body;
+        }
+        value
+    }
+
+    fn responses_json(&self, validated: bool) -> JsonValue {
+        let mut responses = serde_json::Map::new();
+        for resp in self.responses.iter() {
+            let mut value = json!({"description": resp.description});
+            if let Some(schema) = &resp.schema {
+                value["content"] = json!({"application/json": {"schema": schema}});
+            }
+            responses.insert(resp.status.to_string(), value);
+        }
+        if responses.is_empty() {
+            responses.insert(String::from("200"), json!({"description": "Successful Response"}));
+        }
+        // invalid parameters are answered with 422, see `HttpError`
+        if validated && !responses.contains_key("422") {
+            responses.insert(String::from("422"), json!({
+                "description": "Validation Error",
+                "content": {"application/json": {"schema": {
+                    "$ref": "#/components/schemas/HTTPValidationError",
+                }}},
+            }));
+        }
+        JsonValue::Object(responses)
+    }
+}
+
+
+/// Settings of the OpenAPI document served by an `App`, see `App::set_openapi`
+#[derive(Clone, Debug)]
+pub struct OpenApi {
+    pub title: String,
+    pub version: String,
+    pub description: Option<String>,
+    /// path of the JSON document
+    pub path: String,
+    /// path of the HTML page browsing the document, None to disable it
+    pub docs_path: Option<String>,
+}
+
+impl OpenApi {
+    pub fn new(title: &str, version: &str) -> Self {
+        Self {
+            title: String::from(title),
+            version: String::from(version),
+            description: None,
+            path: String::from("/openapi.json"),
+            docs_path: Some(String::from("/docs")),
+        }
+    }
+
+    /// The whole document from the `paths` object of a router
+    pub fn document(&self, paths: JsonValue) -> JsonValue {
+        let mut info = json!({"title": self.title, "version": self.version});
+        if let Some(description) = &self.description {
+            info["description"] = json!(description);
+        }
+        json!({
+            "openapi": "3.1.0",
+            "info": info,
+            "paths": paths,
+            "components": {"schemas": {
+                "ValidationError": {
+                    "type": "object",
+                    "required": ["loc", "msg"],
+                    "properties": {
+                        "loc": {"type": "array", "items": {"type": "string"}},
+                        "msg": {"type": "string"},
+                    },
+                },
+                "HTTPValidationError": {
+                    "type": "object",
+                    "properties": {
+                        "detail": {
+                            "type": "array",
+                            "items": {"$ref": "#/components/schemas/ValidationError"},
+                        },
+                    },
+                },
+            }},
+        })
+    }
+
+    /// HTML page rendering the document with Swagger UI
+    pub fn docs_html(&self) -> String {
+        DOCS_HTML
+            .replace("{{title}}", &self.title)
+            .replace("{{path}}", &self.path)
+    }
+}
+
+
+const DOCS_HTML: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>{{title}}</title>
+    <link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="https://cdn.jsdelivr.net/npm/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>
+        SwaggerUIBundle({url: "{{path}}", dom_id: "#swagger-ui"});
+    </script>
+</body>
+</html>
+"##;
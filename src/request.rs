@@ -2,8 +2,12 @@ use std;
 use std::collections::HashMap;
 use std::io::BufReader;
 use std::net::TcpStream;
+use std::sync::Arc;
 
 use super::http;
+use super::compression::decode_body;
+use super::error::HttpError;
+use super::extensions::Extensions;
 
 mod parser;
 pub mod content_type;
@@ -18,6 +22,31 @@ use parser::{
 };
 
 
+/// Size limits of the request bodies, bigger ones are answered with 413
+pub struct BodyLimits {
+    /// largest body read from the connection
+    pub max_size: usize,
+    /// largest body once its "Content-Encoding" is decoded, a few
+    /// compressed bytes can expand to gigabytes
+    pub max_decoded_size: usize,
+}
+
+impl BodyLimits {
+    pub fn new() -> Self {
+        Self {
+            max_size: 16 * 1024 * 1024,
+            max_decoded_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
 pub struct Request<'a> {
     pub protocol: http::Protocol<'a>,
     pub method: http::Method<'a>,
@@ -28,15 +57,45 @@ pub struct Request<'a> {
     pub fragment: Option<String>,
     pub headers: HashMap<String,String>,
     pub body: ContentType,
+    /// trailer fields sent after a chunked body
+    pub trailers: HashMap<String,String>,
+    /// state of the app serving the request, see `App::with_state`
+    pub state: Arc<Extensions>,
+    /// values attached to this request, e.g. by a middleware for the handler
+    pub extensions: Extensions,
 }
 
 
 impl Request<'_> {
-    pub fn from_stream(stream: &TcpStream) -> Result<Self, String> {
+    pub fn from_stream(stream: &TcpStream) -> Result<Self, HttpError> {
         let mut buf_reader = BufReader::new(stream);
-        let res = match parse_readout(&mut buf_reader) {
-            Ok(v) => v, Err(e) => return Err(e),
-        };
+        Self::from_reader(&mut buf_reader, &BodyLimits::new())
+    }
+
+    /// Placeholder of a request which could not be read, to render its
+    /// error: GET with an empty path and no headers
+    pub fn unparsed() -> Self {
+        Request {
+            protocol: http::PROTOCOL::HTTP_1_1,
+            method: http::METHOD::GET,
+            path: String::new(),
+            username: None,
+            password: None,
+            query: HashMap::new(),
+            fragment: None,
+            headers: HashMap::new(),
+            body: None,
+            trailers: HashMap::new(),
+            state: Arc::new(Extensions::new()),
+            extensions: Extensions::new(),
+        }
+    }
+
+    /// Read one request from a buffered stream. The reader can be reused to
+    /// read the following requests of a persistent connection. The error
+    /// is the response to send, e.g. 400 or 413.
+    pub fn from_reader(buf_reader: &mut BufReader<&TcpStream>, limits: &BodyLimits) -> Result<Self, HttpError> {
+        let res = parse_readout(buf_reader, limits.max_size)?;
         let protocol = res.protocol.unwrap();
         let method = res.method.unwrap();
         let url = res.url.unwrap();
@@ -49,8 +108,20 @@ impl Request<'_> {
         let fragment = match url.fragment() {
             Some(v) => Some(v.to_owned()), None => None,
         };
-        let headers = res.headers.unwrap();
-        let body = res.body.unwrap();
+        let mut headers = res.headers.unwrap();
+        let mut body = res.body.unwrap();
+        // undo "Content-Encoding" before the body is parsed, the headers
+        // then describe the decoded body
+        let encoding = headers.keys()
+            .find(|k| k.eq_ignore_ascii_case("Content-Encoding"))
+            .cloned();
+        if let Some(key) = encoding {
+            let value = headers.remove(&key).unwrap_or_default();
+            body = decode_body(body, &value, limits.max_decoded_size)?;
+            if let Some(key) = headers.keys().find(|k| k.eq_ignore_ascii_case("Content-Length")).cloned() {
+                headers.insert(key, body.len().to_string());
+            }
+        }
         let body_boundary = res.boundary;
         // content-type
         let none = String::from("none");
@@ -58,18 +129,18 @@ impl Request<'_> {
         let content: ContentType = match content_type {
             "multipart/form-data" => {
                 if body_boundary.is_none() {
-                    return Err(String::from("Multipart/form-data boundary not found"));
+                    return Err(HttpError::bad_request("Multipart/form-data boundary not found"));
                 }
                 let boundary = body_boundary.unwrap();
                 match parse_readout_body__multipart(&body, &boundary) {
                     Ok(res) => Some(res),
-                    Err(e) => return Err(e),
+                    Err(e) => return Err(HttpError::bad_request(&e)),
                 }
             },
             "application/x-www-form-urlencoded" => {
                 match parse_readout_body__x_www_form_urlencoded(&body) {
                     Ok(res) => Some(res),
-                    Err(e) => return Err(e),
+                    Err(e) => return Err(HttpError::bad_request(&e)),
                 }
             },
             "application/json" | 
@@ -79,7 +150,7 @@ impl Request<'_> {
             "application/xml" | "text/xml" => {
                 match parse_readout_body__text(&body, content_type) {
                     Ok(res) => Some(res),
-                    Err(e) => return Err(e),
+                    Err(e) => return Err(HttpError::bad_request(&e)),
                 }
             },
             "none" => {
@@ -88,7 +159,7 @@ impl Request<'_> {
             _ => {
                 match parse_readout_body__binary(&body, content_type) {
                     Ok(res) => Some(res),
-                    Err(e) => return Err(e),
+                    Err(e) => return Err(HttpError::bad_request(&e)),
                 }
             }
         };
@@ -102,8 +173,42 @@ impl Request<'_> {
             fragment,
             headers,
             body: content,
+            trailers: res.trailers,
+            state: Arc::new(Extensions::new()),
+            extensions: Extensions::new(),
         })
     }
+
+    /// Cookies sent in the "Cookie" header
+    pub fn cookies(&self) -> HashMap<String, String> {
+        let mut cookies = HashMap::new();
+        let header = match self.header("Cookie") {
+            Some(v) => v,
+            None => return cookies,
+        };
+        for pair in header.split(';') {
+            if let Some((name, value)) = pair.split_once('=') {
+                let value = value.trim();
+                // a value may be quoted
+                let value = value.strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                cookies.insert(name.trim().to_string(), value.to_string());
+            }
+        }
+        cookies
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().remove(name)
+    }
+
+    /// Get header value by case-insensitive name
+    pub fn header(&self, key: &str) -> Option<&String> {
+        self.headers.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
 }
 
 
@@ -116,7 +221,8 @@ impl std::fmt::Display for Request<'_> {
                 path: {},\r\n    \
                 query: {:?},\r\n    \
                 fragment: {:?},\r\n    \
-                headers: {:?},\r\n\
+                headers: {:?},\r\n    \
+                trailers: {:?},\r\n\
             )",
             self.protocol,
             self.method,
@@ -124,6 +230,7 @@ impl std::fmt::Display for Request<'_> {
             self.query,
             self.fragment,
             self.headers,
+            self.trailers,
         )
     }
 }
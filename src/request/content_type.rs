@@ -35,7 +35,8 @@ pub enum RawDataType<'a> {
     Text(&'a String),
     Binary(&'a Vec<u8>),
     File(&'a FileCursor),
-    Multiple(&'a HashMap<String,Box<dyn HasContent>>),
+    /// fields of a form, with every value of a repeated name
+    Multiple(&'a HashMap<String,Vec<Box<dyn HasContent>>>),
     None,
 }
 
@@ -89,12 +90,17 @@ impl HasContent for TextContent {
 
 pub struct FormContent {
     pub content_type: String,
-    pub content: HashMap<String,Box<dyn HasContent>>,
+    /// values of each name in the order they were sent, e.g. `tags=a&tags=b`
+    pub content: HashMap<String,Vec<Box<dyn HasContent>>>,
 }
 impl FormContent {
-    // pub fn get(&self, key: &str) -> Option<&ContentType> {
+    /// Last value of `key`
     pub fn get(&self, key: &str) -> Option<&Box<dyn HasContent>> {
-        self.content.get(key)
+        self.content.get(key).and_then(|v| v.last())
+    }
+    /// Every value of a repeated `key`
+    pub fn get_all(&self, key: &str) -> &[Box<dyn HasContent>] {
+        self.content.get(key).map_or(&[], |v| v.as_slice())
     }
 }
 impl HasContent for FormContent {
@@ -6,6 +6,7 @@ use std::net::TcpStream;
 use url::{Url, form_urlencoded};
 
 use super::http;
+use crate::error::HttpError;
 use super::content_type::{
     FileCursor,
     TextContent,
@@ -30,12 +31,33 @@ pub struct ParseResultData {
     pub headers: Option<HashMap::<String,String>>,
     pub body: Option<Vec<u8>>,
     pub boundary: Option<String>,
+    pub trailers: HashMap::<String,String>,
 }
 
 type BodyResult = Result<Box<dyn HasContent>, String>;
 
+/// Longest chunk-size line of a chunked body, with its extensions
+const MAX_CHUNK_LINE: usize = 4096;
+/// Largest trailer section of a chunked body
+const MAX_TRAILERS_SIZE: usize = 8192;
 
-pub fn parse_readout(buf_reader: &mut BufReader<&TcpStream>) -> Result<ParseResultData, String> {
+/// Request which cannot be parsed, answered with 400
+fn bad_request(message: String) -> HttpError {
+    HttpError::bad_request(&message)
+}
+
+/// Body larger than the limit of the app, answered with 413
+fn too_large(limit: usize) -> HttpError {
+    HttpError::new(413, &format!("Request body is larger than {limit} bytes"))
+}
+
+
+/// Read the request line, the headers and a body of at most
+/// `max_body_size` bytes
+pub fn parse_readout(
+    buf_reader: &mut BufReader<&TcpStream>,
+    max_body_size: usize,
+) -> Result<ParseResultData, HttpError> {
     // HTTP/1.1 Request:
     //   Status-Line
     //   *(( general-header
@@ -45,8 +67,8 @@ pub fn parse_readout(buf_reader: &mut BufReader<&TcpStream>) -> Result<ParseResu
     //   [ message-body ]
     let mut register: Vec<u8> = vec![];
     let mut last: Option<u8> = None;
-    let mut end_of_header = false;
-    let mut cl: u32 = 0;
+    let mut cl: Option<usize> = None;
+    let mut chunked = false;
     let mut iline: u32 = 0;
     // data
     let mut headers = HashMap::<String,String>::new();
@@ -58,9 +80,13 @@ pub fn parse_readout(buf_reader: &mut BufReader<&TcpStream>) -> Result<ParseResu
         body: None,
         boundary: None,
         query: HashMap::<String,String>::new(),
+        trailers: HashMap::<String,String>::new(),
     };
-    for byte in buf_reader.bytes() {
-        let v = byte.unwrap();
+    for byte in buf_reader.by_ref().bytes() {
+        let v = match byte {
+            Ok(v) => v,
+            Err(e) => return Err(bad_request(format!("Fail to read request: {e}"))),
+        };
         if let Some(_v) = last {
             // not linesep, append to register
             if !http::is_CRLF_bytes(&[_v,v]) {
@@ -71,11 +97,16 @@ pub fn parse_readout(buf_reader: &mut BufReader<&TcpStream>) -> Result<ParseResu
             // meet linesep line
             if let Ok(line) = std::str::from_utf8(&register) {
                 let _line = line.to_string();
-                if _line == "" {
+                if _line.is_empty() && iline == 0 {
+                    // empty lines before the request line, e.g. a CRLF
+                    // after the body of the previous request, are ignored,
+                    // RFC 9112 section 2.2
+                    register.clear();
+                    last = None;
+                    continue;
+                } else if _line.is_empty() {
                     // blank line as the separator of header and body
-                    end_of_header = true;
-                    if cl <= 1 { break; }
-                    cl -= 1;
+                    break;
                 } else if iline == 0 {
                     match parse_readout_status_line(_line) {
                         Ok(v) => {
@@ -84,26 +115,55 @@ pub fn parse_readout(buf_reader: &mut BufReader<&TcpStream>) -> Result<ParseResu
                             result.query = parse_urlencoded(v.2.query().unwrap_or(""));
                             result.url = Some(v.2);
                         },
-                        Err(e) => return Err(e),
+                        Err(e) => return Err(bad_request(e)),
                     }
                 } else {
                     match parse_readout_header_line(&_line) {
                         Ok(h) => {
                             let (hk, hv) = (h.key, h.value);
-                            if hk == "Content-Length" {
-                                cl = hv.parse().unwrap();
+                            if hk.eq_ignore_ascii_case("Content-Length") {
+                                // repeated values, in one or several headers,
+                                // must agree, RFC 9112 section 6.3
+                                for v in hv.split(',') {
+                                    let len: usize = match v.trim().parse() {
+                                        Ok(v) => v,
+                                        Err(_) => return Err(bad_request(format!("Invalid Content-Length {hv}"))),
+                                    };
+                                    if cl.is_some_and(|cl| cl != len) {
+                                        return Err(bad_request(String::from("Different Content-Length values")));
+                                    }
+                                    cl = Some(len);
+                                }
                                 headers.insert(hk, hv);
-                            } else if hk == "Content-Type" {
+                            } else if hk.eq_ignore_ascii_case("Transfer-Encoding") {
+                                // only chunked is decoded, other codings would be
+                                // passed to the handler as the body, RFC 9112 section 6.1
+                                for coding in hv.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+                                    if !coding.eq_ignore_ascii_case("chunked") {
+                                        return Err(HttpError::new(501, &format!("Unsupported Transfer-Encoding {hv}")));
+                                    }
+                                    if chunked {
+                                        return Err(bad_request(String::from("Transfer-Encoding chunked applied more than once")));
+                                    }
+                                    chunked = true;
+                                }
+                                if !chunked {
+                                    return Err(bad_request(format!("Invalid Transfer-Encoding {hv}")));
+                                }
+                                headers.insert(hk, hv);
+                            } else if hk.eq_ignore_ascii_case("Content-Type") {
                                 if let Some(b) = h.metadata.get("boundary") {
                                     result.boundary = Some(b.to_string());
-                                } else {
-                                    headers.insert(hk, hv);
                                 }
+                                // without the parameters, to pick the body parser
+                                headers.insert(String::from("Content-Type"), hv);
                             } else {
-                                headers.insert(hk, hv);
+                                // keep the parameters after ';', e.g. all
+                                // the cookies or the q-values of Accept
+                                headers.insert(hk, raw_header_value(&_line));
                             }
                         },
-                        Err(e) => return Err(e),
+                        Err(e) => return Err(bad_request(e)),
                     }
                 }
                 iline += 1;
@@ -114,23 +174,131 @@ pub fn parse_readout(buf_reader: &mut BufReader<&TcpStream>) -> Result<ParseResu
                 register.push(_v);
                 last = Some(v);
             }
-        } else if end_of_header { // body
-            register.push(v);
-            if cl > 0 {
-                cl -= 1;
-            } else {
-                break;
-            }
         } else { // first or new line
             last = Some(v);
         }
     }
+    if result.protocol.is_none() {
+        return Err(bad_request(String::from("Connection closed before the request line")));
+    }
+    // message body
+    let body = if chunked {
+        // request smuggling: never guess which framing header is right
+        if cl.is_some() {
+            return Err(bad_request(String::from(
+                "Request must not contain both Transfer-Encoding and Content-Length"
+            )));
+        }
+        let (body, trailers) = parse_readout_chunked(buf_reader, max_body_size)?;
+        result.trailers = trailers;
+        body
+    } else {
+        let len = cl.unwrap_or(0);
+        // checked before reading, the length is the client's word
+        if len > max_body_size {
+            return Err(too_large(max_body_size));
+        }
+        read_body(buf_reader, len)?
+    };
     result.headers = Some(headers);
-    result.body = Some(register);
+    result.body = Some(body);
     Ok(result)
 }
 
 
+/// Read `len` bytes into a buffer growing with the data actually received
+fn read_body(buf_reader: &mut BufReader<&TcpStream>, len: usize) -> Result<Vec<u8>, HttpError> {
+    let mut body: Vec<u8> = vec![];
+    if let Err(e) = buf_reader.by_ref().take(len as u64).read_to_end(&mut body) {
+        return Err(bad_request(format!("Fail to read request body: {e}")));
+    }
+    if body.len() < len {
+        return Err(bad_request(String::from("Connection closed in the middle of the body")));
+    }
+    Ok(body)
+}
+
+
+/// Read a line ending with CRLF of at most `max` bytes, a longer line is
+/// answered with `status`
+fn read_line_crlf(
+    buf_reader: &mut BufReader<&TcpStream>,
+    max: usize,
+    status: usize,
+) -> Result<String, HttpError> {
+    let mut line: Vec<u8> = vec![];
+    let limit = max as u64 + 2;
+    match buf_reader.by_ref().take(limit).read_until(b'\n', &mut line) {
+        Ok(0) => return Err(bad_request(String::from("Connection closed in the middle of the body"))),
+        Ok(_) => {},
+        Err(e) => return Err(bad_request(format!("Fail to read request body: {e}"))),
+    }
+    if !line.ends_with(b"\n") && line.len() as u64 == limit {
+        return Err(HttpError::new(status, &format!("Chunked body line is longer than {max} bytes")));
+    }
+    if !line.ends_with(http::CRLF.as_bytes()) {
+        return Err(bad_request(String::from("Chunked body line must end with CRLF")));
+    }
+    line.truncate(line.len() - 2);
+    match String::from_utf8(line) {
+        Ok(v) => Ok(v),
+        Err(_) => Err(bad_request(String::from("Fail to convert binary to string."))),
+    }
+}
+
+
+/// Decode body with "Transfer-Encoding: chunked"
+///   chunked-body = *chunk last-chunk trailer-section CRLF
+///   chunk        = chunk-size [ chunk-ext ] CRLF chunk-data CRLF
+///   last-chunk   = 1*("0") [ chunk-ext ] CRLF
+/// Chunk extensions are ignored. Returns the body and the trailer fields.
+/// Chunk lines are limited to `MAX_CHUNK_LINE` bytes (400) and the
+/// trailer section to `MAX_TRAILERS_SIZE` bytes (431).
+fn parse_readout_chunked(
+    buf_reader: &mut BufReader<&TcpStream>,
+    max_body_size: usize,
+) -> Result<(Vec<u8>, HashMap<String,String>), HttpError> {
+    let mut body: Vec<u8> = vec![];
+    loop {
+        let line = read_line_crlf(buf_reader, MAX_CHUNK_LINE, 400)?;
+        let size_str = match line.split_once(";") {
+            Some((size, _ext)) => size,
+            None => &line,
+        }.trim();
+        let size = match usize::from_str_radix(size_str, 16) {
+            Ok(v) => v,
+            Err(_) => return Err(bad_request(format!("Invalid chunk size {size_str}"))),
+        };
+        if size == 0 {
+            break;
+        }
+        if size > max_body_size - body.len() {
+            return Err(too_large(max_body_size));
+        }
+        body.extend(read_body(buf_reader, size)?);
+        if read_body(buf_reader, 2)? != http::CRLF.as_bytes() {
+            return Err(bad_request(String::from("Chunk data must be followed by CRLF")));
+        }
+    }
+    // trailer section
+    let mut trailers = HashMap::<String,String>::new();
+    let mut size = 0;
+    loop {
+        let line = read_line_crlf(buf_reader, MAX_TRAILERS_SIZE - size, 431)?;
+        if line.is_empty() {
+            break;
+        }
+        size += line.len() + 2;
+        if size > MAX_TRAILERS_SIZE {
+            return Err(HttpError::new(431, &format!("Trailer section is larger than {MAX_TRAILERS_SIZE} bytes")));
+        }
+        let h = parse_readout_header_line(&line).map_err(bad_request)?;
+        trailers.insert(h.key, h.value);
+    }
+    Ok((body, trailers))
+}
+
+
 pub fn parse_urlencoded(s: &str) -> HashMap<String,String> {
     let mut tmp = HashMap::<String,String>::new();
     let mut pairs = form_urlencoded::parse(s.as_bytes());
@@ -179,6 +347,14 @@ fn parse_readout_status_line(line: String) -> Result<(http::Protocol<'static>, h
 }
 
 
+/// Value of a header line as sent
+fn raw_header_value(line: &str) -> String {
+    match line.split_once(':') {
+        Some((_, value)) => value.trim().to_string(),
+        None => String::new(),
+    }
+}
+
 fn parse_readout_header_line(line: &str) -> Result<HeaderLine, String> {
     // Example line:
     // Content-Disposition: form-data; name=\"b\"
@@ -232,13 +408,13 @@ pub fn parse_readout_body__text(buf: &Vec<u8>, content_type: &str) -> BodyResult
 /// - application/x-www-form-urlencoded
 pub fn parse_readout_body__x_www_form_urlencoded(buf: &Vec<u8>) -> BodyResult {
     if let Ok(s) = std::str::from_utf8(&buf) {
-        let mut res = HashMap::<String,Box<dyn HasContent>>::new();
-        for (k,v) in parse_urlencoded(s) {
-            res.insert(
-                k,
+        let mut res = HashMap::<String,Vec<Box<dyn HasContent>>>::new();
+        // a repeated name keeps all its values
+        for (k,v) in form_urlencoded::parse(s.as_bytes()) {
+            res.entry(k.into_owned()).or_default().push(
                 Box::new(TextContent {
                     content_type: "".to_string(),
-                    content: v,
+                    content: v.into_owned(),
                 })
             );
         }
@@ -289,10 +465,12 @@ pub fn parse_readout_body__multipart(buf: &Vec<u8>, boundary: &str) -> BodyResul
             }
             // meet linesep
             let n = register.len();
-            if writting_content && n > l+2
-                && (&register[n-l..n] == sep || &register[n-l-2..n-2] == sep) {
+            let closing = n >= l+4 && &register[n-l-2..n-2] == sep && register.ends_with(b"--");
+            if writting_content && n >= l+2 && (&register[n-l..n] == sep || closing) {
                 // register: ...\r\n-------xxxxxxxxx
-                let content = &register[..n-l-2].to_vec();
+                // or the last one: ...\r\n-------xxxxxxxxx--
+                let end = if closing { n-l-4 } else { n-l-2 };
+                let content = &register[..end].to_vec();
                 blocks.push((header_lines.clone(), content.clone()));
                 header_lines.clear();
                 register.clear();
@@ -328,24 +506,24 @@ pub fn parse_readout_body__multipart(buf: &Vec<u8>, boundary: &str) -> BodyResul
             last = Some(*v);
         }
     }
-    let mut res = HashMap::<String,Box<dyn HasContent>>::new();
+    let mut res = HashMap::<String,Vec<Box<dyn HasContent>>>::new();
     for (block,content) in blocks.iter() {
+        // header names are case-insensitive
         let mut headers = HashMap::<String, HeaderLine>::new();
         for line in block.iter() {
             if let Ok(header) = parse_readout_header_line(line) {
-                headers.insert(header.key.to_string(), header);
+                headers.insert(header.key.to_ascii_lowercase(), header);
             }
         }
-        if let Some(header) = headers.get("content-Disposition") {
+        if let Some(header) = headers.get("content-disposition") {
             if let Some(key) = header.metadata.get("name") {
-                let content_type = match header.metadata.get("Content-Type") {
-                    Some(v) => v.to_string(),
+                let content_type = match headers.get("content-type") {
+                    Some(v) => v.value.to_string(),
                     None => "".to_string(),
                 };
                 if let Some(filename) = header.metadata.get("filename") {
                     // File
-                    res.insert(
-                        key.to_string(),
+                    res.entry(key.to_string()).or_default().push(
                         Box::new(FileContent {
                             filename: filename.to_string(),
                             filename_encoded: {
@@ -361,8 +539,7 @@ pub fn parse_readout_body__multipart(buf: &Vec<u8>, boundary: &str) -> BodyResul
                         })
                     );
                 } else if let Ok(s) = std::str::from_utf8(content) {
-                    res.insert(
-                        key.to_string(),
+                    res.entry(key.to_string()).or_default().push(
                         Box::new(TextContent {
                             content_type,
                             content: s.to_string(),
@@ -7,5 +7,11 @@ pub use text_like::*;
 mod json;
 pub use json::*;
 
+mod stream;
+pub use stream::*;
+
+mod empty;
+pub use empty::*;
+
 mod template;
 pub use template::Template;
@@ -1,10 +1,82 @@
-use std::io::Write;
+use std::io::{Read, Write, BufWriter};
 use std::net::TcpStream;
 use std::collections::HashMap;
 
 use crate::http::{self, STATUS_SP, HEADER_SP, HEADER_META_SP, CRLF};
 
 
+/// Size of the chunks read from a streaming body
+pub const STREAM_CHUNK_SIZE: usize = 8192;
+
+pub type BodyChunks = Box<dyn Iterator<Item = std::io::Result<Vec<u8>>> + Send>;
+
+/// Messege body of a response
+pub enum Body {
+    /// the whole body is in memory
+    Full(Vec<u8>),
+    /// the body is produced chunk by chunk while it is written
+    Stream(BodyChunks),
+}
+
+impl Body {
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Body::Full(bytes)
+    }
+
+    /// Stream the body from a reader, e.g. a file
+    pub fn from_reader<R>(reader: R) -> Self
+    where R: Read + Send + 'static
+    {
+        Body::Stream(Box::new(ReaderChunks { reader, done: false }))
+    }
+
+    /// Stream the body from generated chunks, e.g. rows of an export
+    pub fn from_chunks<I>(chunks: I) -> Self
+    where I: Iterator<Item = Vec<u8>> + Send + 'static
+    {
+        Body::Stream(Box::new(chunks.map(Ok)))
+    }
+
+    /// Chunks of the body, a full body is a single chunk
+    pub fn into_chunks(self) -> BodyChunks {
+        match self {
+            Body::Full(bytes) => Box::new(std::iter::once(Ok(bytes))),
+            Body::Stream(chunks) => chunks,
+        }
+    }
+}
+
+struct ReaderChunks<R: Read> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> Iterator for ReaderChunks<R> {
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut buf = vec![0; STREAM_CHUNK_SIZE];
+        match self.reader.read(&mut buf) {
+            Ok(0) => {
+                self.done = true;
+                None
+            },
+            Ok(n) => {
+                buf.truncate(n);
+                Some(Ok(buf))
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+
 pub trait MakeResponse {
     // HTTP Response:
     //   Status-Line
@@ -20,7 +92,8 @@ pub trait MakeResponse {
     fn protocol(&self) -> &http::Protocol<'static>;
     fn status(&self) -> &http::Status<'static>;
     fn headers(&self) -> &http::Headers; // key,value,metadata
-    fn messege_body(&self) -> Vec<Vec<u8>>;
+    fn headers_mut(&mut self) -> &mut http::Headers;
+    fn into_body(self: Box<Self>) -> Body;
 
     // derived attributes
     fn status_line(&self) -> String {
@@ -45,12 +118,23 @@ pub trait MakeResponse {
         s
     }
 
+    /// Whether the body is sent with "Transfer-Encoding: chunked"
+    fn is_chunked(&self) -> bool {
+        self.headers().iter().any(|(k, (v, _))| {
+            k.eq_ignore_ascii_case("Transfer-Encoding") && v.eq_ignore_ascii_case("chunked")
+        })
+    }
+
     // methods
-    fn print_response(&self) {
+    fn print_response(self: Box<Self>) {
         print!("{}", self.status_line());
         print!("{}", self.header_lines());
         print!("{CRLF}");
-        for bytes in self.messege_body() {
+        let chunks: BodyChunks = match self.into_body() {
+            Body::Full(bytes) => Box::new(std::iter::once(Ok(bytes))),
+            Body::Stream(chunks) => chunks,
+        };
+        for bytes in chunks.flatten() {
             if let Ok(s) = std::str::from_utf8(&bytes) {
                 print!("{s}");
             } else {
@@ -59,21 +143,59 @@ pub trait MakeResponse {
         }
     }
 
-    fn write(&self, mut stream: TcpStream) -> std::io::Result<()> {
-        stream.write_all(self.status_line().as_bytes())?;
-        stream.write_all(self.header_lines().as_bytes())?;
-        stream.write_all(CRLF.as_bytes())?;
-        for bytes in self.messege_body() {
-            stream.write_all(&bytes)?;
+    /// Write the status line and headers only, as the answer to HEAD.
+    /// The headers, e.g. Content-Length, stay those of the full response.
+    fn write_head(self: Box<Self>, stream: &TcpStream) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(stream);
+        writer.write_all(self.status_line().as_bytes())?;
+        writer.write_all(self.header_lines().as_bytes())?;
+        writer.write_all(CRLF.as_bytes())?;
+        writer.flush()
+    }
+
+    fn write(self: Box<Self>, stream: &TcpStream) -> std::io::Result<()> {
+        let chunked = self.is_chunked();
+        let mut writer = BufWriter::new(stream);
+        writer.write_all(self.status_line().as_bytes())?;
+        writer.write_all(self.header_lines().as_bytes())?;
+        writer.write_all(CRLF.as_bytes())?;
+        match self.into_body() {
+            Body::Full(bytes) => {
+                write_body_chunk(&mut writer, &bytes, chunked)?;
+            },
+            Body::Stream(chunks) => {
+                // drain the body incrementally, only one chunk is kept in memory
+                for bytes in chunks {
+                    write_body_chunk(&mut writer, &bytes?, chunked)?;
+                }
+            },
+        }
+        if chunked {
+            // last-chunk and an empty trailer section
+            writer.write_all(format!("0{CRLF}{CRLF}").as_bytes())?;
         }
-        Ok(())
+        writer.flush()
     }
 }
 
+fn write_body_chunk<W: Write>(writer: &mut W, bytes: &[u8], chunked: bool) -> std::io::Result<()> {
+    if !chunked {
+        return writer.write_all(bytes);
+    }
+    // an empty chunk would be taken as the last-chunk
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    writer.write_all(format!("{:X}{CRLF}", bytes.len()).as_bytes())?;
+    writer.write_all(bytes)?;
+    writer.write_all(CRLF.as_bytes())
+}
+
 pub struct MakeContentData {
     pub content_type_headers: http::Headers,
     pub content_type: String,
-    pub content_length: usize,
+    /// None if the length is unknown until the body is sent
+    pub content_length: Option<usize>,
 }
 
 pub trait MakeContent {
@@ -82,17 +204,27 @@ pub trait MakeContent {
         let data = self.data();
         let mut headers = http::Headers::new();
         headers.extend(data.content_type_headers);
-        headers.insert(
-            String::from("Content-Length"),
-            (data.content_length.to_string(), vec![])
-        );
+        match data.content_length {
+            Some(content_length) => headers.insert(
+                String::from("Content-Length"),
+                (content_length.to_string(), vec![])
+            ),
+            None => headers.insert(
+                String::from("Transfer-Encoding"),
+                (String::from("chunked"), vec![])
+            ),
+        };
         headers.insert(
             String::from("Content-Type"),
             (data.content_type.to_string(), vec![])
         );
         headers
     }
-    fn into_bytes(&self) -> Vec<u8>;
+    /// The whole body, the content is consumed
+    fn into_bytes(self) -> Vec<u8> where Self: Sized;
+    fn into_body(self) -> Body where Self: Sized {
+        Body::Full(self.into_bytes())
+    }
 }
 
 
@@ -118,6 +250,20 @@ impl<T: MakeContent> Response<T> {
             content,
         })
     }
+
+    /// Set the "ETag", e.g. from `conditional::make_strong_etag`
+    pub fn with_etag(mut self, etag: &str) -> Self {
+        self.headers.insert(String::from("ETag"), (String::from(etag), vec![]));
+        self
+    }
+
+    pub fn with_last_modified(mut self, time: std::time::SystemTime) -> Self {
+        self.headers.insert(
+            String::from("Last-Modified"),
+            (http::format_http_date(time), vec![]),
+        );
+        self
+    }
 }
 
 impl<T: MakeContent> MakeResponse for Response<T> {
@@ -130,8 +276,10 @@ impl<T: MakeContent> MakeResponse for Response<T> {
     fn headers(&self) -> &http::Headers {
         &self.headers
     }
-    fn messege_body(&self) -> Vec<Vec<u8>> {
-        let vec: Vec<Vec<u8>> = vec![self.content.into_bytes()];
-        vec
+    fn headers_mut(&mut self) -> &mut http::Headers {
+        &mut self.headers
+    }
+    fn into_body(self: Box<Self>) -> Body {
+        self.content.into_body()
     }
 }
@@ -0,0 +1,40 @@
+use crate::http;
+
+use super::{Response, MakeContent, MakeContentData};
+
+
+/// Response without a body, e.g. 204, 304 or a redirect.
+/// 1xx, 204 and 304 have no framing headers; the others send
+/// "Content-Length: 0", so that a persistent connection knows where the
+/// body ends.
+pub fn make_empty_response(status_code: usize) -> Result<Response<MakeEmptyContent>,String> {
+    let mut headers = http::Headers::new();
+    if status_code >= 200 && status_code != 204 && status_code != 304 {
+        headers.insert(String::from("Content-Length"), (String::from("0"), vec![]));
+    }
+    Response::<MakeEmptyContent>::new(
+        status_code,
+        headers,
+        MakeEmptyContent,
+    )
+}
+
+
+pub struct MakeEmptyContent;
+
+impl MakeContent for MakeEmptyContent {
+    fn data(&self) -> MakeContentData {
+        MakeContentData {
+            content_type_headers: http::Headers::new(),
+            content_type: String::new(),
+            content_length: Some(0),
+        }
+    }
+    // no Content-Type nor Content-Length
+    fn headers(&self) -> http::Headers {
+        http::Headers::new()
+    }
+    fn into_bytes(self) -> Vec<u8> {
+        vec![]
+    }
+}
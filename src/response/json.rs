@@ -23,12 +23,12 @@ impl MakeContent for MakeJsonContent {
             content_type_headers: http::Headers::new(),
             content_type: String::from("application/json"),
             content_length: match json_dump(&self.0) {
-                Ok(v) => v.len(),
-                _ => 0,
+                Ok(v) => Some(v.len()),
+                _ => Some(0),
             }
         }
     }
-    fn into_bytes(&self) -> Vec<u8> {
+    fn into_bytes(self) -> Vec<u8> {
         if let Ok(v) = json_dump(&self.0) {
             v.into_bytes()
         } else {
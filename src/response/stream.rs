@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use crate::http;
+
+use super::{Response, Body, MakeContent, MakeContentData};
+
+
+/// Make a response whose body is written while it is produced.
+/// If `content_length` is None the body is sent with
+/// "Transfer-Encoding: chunked".
+pub fn make_stream_response(
+    status_code: usize,
+    content_type: &str,
+    content_length: Option<usize>,
+    body: Body,
+) -> Result<Response<MakeStreamContent>,String> {
+    Response::<MakeStreamContent>::new(
+        status_code,
+        HashMap::new(),
+        MakeStreamContent::new(content_type, content_length, body),
+    )
+}
+
+
+pub struct MakeStreamContent {
+    pub content_type: String,
+    pub content_length: Option<usize>,
+    body: Body,
+}
+
+impl MakeStreamContent {
+    pub fn new(content_type: &str, content_length: Option<usize>, body: Body) -> Self {
+        Self {
+            content_type: content_type.to_string(),
+            content_length,
+            body,
+        }
+    }
+}
+
+impl MakeContent for MakeStreamContent {
+    fn data(&self) -> MakeContentData {
+        MakeContentData {
+            content_type_headers: http::Headers::new(),
+            content_type: self.content_type.clone(),
+            content_length: self.content_length,
+        }
+    }
+    /// Collect the whole stream into memory
+    fn into_bytes(self) -> Vec<u8> {
+        match self.body {
+            Body::Full(bytes) => bytes,
+            Body::Stream(chunks) => chunks.flatten().flatten().collect(),
+        }
+    }
+    fn into_body(self) -> Body {
+        self.body
+    }
+}
@@ -23,12 +23,12 @@ impl MakeContent for MakeTextLikeContent {
     fn data(&self) -> MakeContentData {
         MakeContentData {
             content_type_headers: http::Headers::new(),
-            content_type: self.content.clone(),
-            content_length: self.content.len(),
+            content_type: self.content_type.clone(),
+            content_length: Some(self.content.len()),
         }
     }
-    fn into_bytes(&self) -> Vec<u8> {
-        self.content.clone().into_bytes()
+    fn into_bytes(self) -> Vec<u8> {
+        self.content.into_bytes()
     }
 }
 
@@ -40,11 +40,11 @@ impl MakeContent for MakeHtmlContent {
         MakeContentData {
             content_type_headers: http::Headers::new(),
             content_type: String::from("text/html"),
-            content_length: self.0.len(),
+            content_length: Some(self.0.len()),
         }
     }
-    fn into_bytes(&self) -> Vec<u8> {
-        self.0.clone().into_bytes()
+    fn into_bytes(self) -> Vec<u8> {
+        self.0.into_bytes()
     }
 }
 
@@ -56,11 +56,11 @@ impl MakeContent for MakeTextContent {
         MakeContentData {
             content_type_headers: http::Headers::new(),
             content_type: String::from("text/plain"),
-            content_length: self.0.len(),
+            content_length: Some(self.0.len()),
         }
     }
-    fn into_bytes(&self) -> Vec<u8> {
-        self.0.clone().into_bytes()
+    fn into_bytes(self) -> Vec<u8> {
+        self.0.into_bytes()
     }
 }
 
@@ -72,11 +72,11 @@ impl MakeContent for MakeCssContent {
         MakeContentData {
             content_type_headers: http::Headers::new(),
             content_type: String::from("text/css"),
-            content_length: self.0.len(),
+            content_length: Some(self.0.len()),
         }
     }
-    fn into_bytes(&self) -> Vec<u8> {
-        self.0.clone().into_bytes()
+    fn into_bytes(self) -> Vec<u8> {
+        self.0.into_bytes()
     }
 }
 
@@ -88,11 +88,11 @@ impl MakeContent for MakeXmlContent {
         MakeContentData {
             content_type_headers: http::Headers::new(),
             content_type: String::from("application/xml"),
-            content_length: self.0.len(),
+            content_length: Some(self.0.len()),
         }
     }
-    fn into_bytes(&self) -> Vec<u8> {
-        self.0.clone().into_bytes()
+    fn into_bytes(self) -> Vec<u8> {
+        self.0.into_bytes()
     }
 }
 
@@ -104,10 +104,10 @@ impl MakeContent for MakeJavascriptContent {
         MakeContentData {
             content_type_headers: http::Headers::new(),
             content_type: String::from("application/javascript"),
-            content_length: self.0.len(),
+            content_length: Some(self.0.len()),
         }
     }
-    fn into_bytes(&self) -> Vec<u8> {
-        self.0.clone().into_bytes()
+    fn into_bytes(self) -> Vec<u8> {
+        self.0.into_bytes()
     }
 }
@@ -1,31 +1,214 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use regex::Regex;
 
+mod tree;
+
+use tree::{Node, Segment};
+
 
 use crate::http::{
     Method,
+    METHOD,
     get_method_from_str,
 };
-use crate::response::{MakeResponse, Response};
+use crate::response::{MakeResponse, Response, make_empty_response};
+use crate::error::HttpError;
 use crate::request::Request;
+use crate::middleware::{Middleware, RenderError, run_middlewares};
+use crate::json::{JsonValue, json};
+use crate::openapi::{Operation, OpenApi, Parameter};
+use crate::schema::Location;
+use crate::static_files::StaticFiles;
+
+
+pub type ResponseResult = Result<Box<dyn MakeResponse>,HttpError>;
+type Callback = Box<dyn Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + Sync + 'static>;
+
+/// Converter of a path parameter, written as `{name:converter}`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Converter {
+    /// `{name}` or `{name:str}`, one segment
+    Str,
+    /// `{name:int}`, digits only
+    Int,
+    /// `{name:slug}`, letters, digits, '-' and '_'
+    Slug,
+    /// `{name:uuid}`, e.g. 123e4567-e89b-12d3-a456-426614174000
+    Uuid,
+    /// `{name:path}`, the rest of the path including '/'
+    Path,
+    /// `{name:[A-Z]{3}}`, any other converter is taken as a regex
+    Regex(String),
+}
 
+impl Converter {
+    fn from_spec(spec: &str) -> Self {
+        match spec {
+            "" | "str" => Converter::Str,
+            "int" => Converter::Int,
+            "slug" => Converter::Slug,
+            "uuid" => Converter::Uuid,
+            "path" => Converter::Path,
+            _ => Converter::Regex(String::from(spec)),
+        }
+    }
+
+    pub fn pattern(&self) -> &str {
+        match self {
+            Converter::Str => "[^/#?]+",
+            Converter::Int => "[0-9]+",
+            Converter::Slug => "[A-Za-z0-9_-]+",
+            Converter::Uuid => "[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+            Converter::Path => "[^#?]+",
+            Converter::Regex(re) => re,
+        }
+    }
+
+    /// JSON schema of the accepted values
+    pub fn json_schema(&self) -> JsonValue {
+        match self {
+            Converter::Str | Converter::Path => json!({"type": "string"}),
+            Converter::Int => json!({"type": "integer", "minimum": 0}),
+            Converter::Uuid => json!({"type": "string", "format": "uuid"}),
+            _ => json!({"type": "string", "pattern": format!("^{}$", self.pattern())}),
+        }
+    }
+}
+
+
+/// Part of a route path
+#[derive(Clone, Debug, PartialEq)]
+enum PathPart {
+    Static(String),
+    Param(String, Converter),
+}
+
+/// Split a route path like `/users/{id:int}/files/{rest:path}` into its
+/// static parts and parameters. Braces inside a converter, e.g. `{3}` in
+/// `{code:[A-Z]{3}}`, are kept.
+fn parse_path(path: &str) -> Vec<PathPart> {
+    let mut parts = vec![];
+    let mut literal = String::new();
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c == '}' {
+            panic!("unmatched '}}' in path '{path}'");
+        }
+        if c != '{' {
+            literal.push(c);
+            continue;
+        }
+        let mut depth = 1;
+        let mut param = String::new();
+        for c in chars.by_ref() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {},
+            }
+            if depth == 0 { break; }
+            param.push(c);
+        }
+        if depth != 0 {
+            panic!("unmatched '{{' in path '{path}'");
+        }
+        let (name, spec) = match param.split_once(':') {
+            Some((name, spec)) => (name, spec),
+            None => (param.as_str(), ""),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            panic!("invalid parameter name '{name}' in path '{path}'");
+        }
+        if !literal.is_empty() {
+            parts.push(PathPart::Static(std::mem::take(&mut literal)));
+        }
+        parts.push(PathPart::Param(String::from(name), Converter::from_spec(spec)));
+    }
+    if !literal.is_empty() {
+        parts.push(PathPart::Static(literal));
+    }
+    parts
+}
+
+/// Split a route path into the segments of the route tree
+fn path_segments(path: &str) -> Vec<Segment> {
+    let mut segments = vec![];
+    let mut current: Vec<PathPart> = vec![];
+    for part in parse_path(path) {
+        match part {
+            PathPart::Static(s) => {
+                let mut pieces = s.split('/');
+                if let Some(first) = pieces.next() {
+                    if !first.is_empty() {
+                        current.push(PathPart::Static(String::from(first)));
+                    }
+                }
+                for piece in pieces {
+                    segments.push(make_segment(std::mem::take(&mut current)));
+                    if !piece.is_empty() {
+                        current.push(PathPart::Static(String::from(piece)));
+                    }
+                }
+            },
+            param => current.push(param),
+        }
+    }
+    segments.push(make_segment(current));
+    // the rest of the path is matched by the regex of the route
+    if let Some(i) = segments.iter().position(|s| matches!(s, Segment::CatchAll)) {
+        segments.truncate(i + 1);
+    }
+    segments
+}
+
+fn make_segment(parts: Vec<PathPart>) -> Segment {
+    if parts.iter().any(|p| matches!(p, PathPart::Param(_, Converter::Path))) {
+        return Segment::CatchAll;
+    }
+    if parts.iter().all(|p| matches!(p, PathPart::Static(_))) {
+        let mut s = String::new();
+        for part in parts {
+            if let PathPart::Static(v) = part { s.push_str(&v); }
+        }
+        return Segment::Static(s);
+    }
+    let constrained = !matches!(parts.as_slice(), [PathPart::Param(_, Converter::Str)]);
+    let mut pattern = String::from("^");
+    for part in parts {
+        match part {
+            PathPart::Static(s) => pattern.push_str(&regex::escape(&s)),
+            PathPart::Param(_, converter) => {
+                pattern.push_str(&format!("(?:{})", converter.pattern()));
+            },
+        }
+    }
+    pattern.push('$');
+    Segment::Param { pattern, constrained }
+}
 
-pub type ResponseResult = Result<Box<dyn MakeResponse>,String>;
-type Callback = Box<dyn Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + 'static>;
 
 struct Route<'a> {
     method: Method<'static>,
-    path: &'a str,
+    path: Cow<'a, str>,
     re: Regex,
     f: Callback,
+    /// middlewares of the included routers, the outermost first
+    middlewares: Vec<Arc<dyn Middleware>>,
+    doc: Operation,
 }
 
 impl<'a> Route<'a> {
     fn new<F>(path: &'a str, method: &str, f: F) -> Self
     where
-        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + 'static 
+        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + Sync + 'static 
     {
+        Self::from_callback(Cow::Borrowed(path), get_method_from_str(method).unwrap(), Box::new(f))
+    }
+
+    fn from_callback(path: Cow<'a, str>, method: Method<'static>, f: Callback) -> Self {
         // validate path 
         if !path.starts_with("/") {
             panic!(
@@ -33,26 +216,41 @@ impl<'a> Route<'a> {
             );
         }
         // extract path arguments:
-        //   path format: /aaa/bbb/{id}...
-        //   regex: /aaa/bbb/(?<id>[^\/\#\?]+)/...$
-        //   replace { to (?<
-        //   replace } to >[^\/\#\?]+)
-        let pattern = String::from(path)
-            .replace(".", "\\.")
-            .replace("{", "(?<")
-            .replace("}", ">[^/#?]+)");
-        let pattern = format!("^{pattern}$");
-        let re = Regex::new(&pattern).unwrap();
+        //   path format: /aaa/bbb/{id:int}...
+        //   regex: ^/aaa/bbb/(?<id>[0-9]+)/...$
+        let mut pattern = String::from("^");
+        for part in parse_path(&path) {
+            match part {
+                PathPart::Static(s) => pattern.push_str(&regex::escape(&s)),
+                PathPart::Param(name, converter) => {
+                    pattern.push_str(&format!("(?<{name}>{})", converter.pattern()));
+                },
+            }
+        }
+        pattern.push('$');
+        let re = match Regex::new(&pattern) {
+            Ok(re) => re,
+            Err(e) => panic!("invalid path '{path}': {e}"),
+        };
         Route {
-            method: get_method_from_str(method).unwrap(),
+            method,
             path,
             re,
-            f: Box::new(f),
+            f,
+            middlewares: vec![],
+            doc: Operation::new(),
         }
     }
 
-    fn execute(&self, path: &str, request: &Request) -> Option<ResponseResult> {
-        println!("54: matching {} with {}:", path, self.re.as_str());
+    /// Call the handler inside the `outer` middlewares and those of the route,
+    /// its errors are rendered with `render` before the after hooks
+    fn execute(
+        &self,
+        path: &str,
+        request: &mut Request,
+        outer: &[Arc<dyn Middleware>],
+        render: &RenderError,
+    ) -> Option<ResponseResult> {
         let path_args: HashMap<String, String> = match self.re.captures(path) {
             Some(caps) => {
                 self.re.capture_names()
@@ -65,98 +263,103 @@ impl<'a> Route<'a> {
             None => return None,
         };
         println!("{path_args:?}");
-        Some(self.f.as_ref()((request, path_args)))
-        // self.f.as_ref()()
+        if outer.is_empty() && self.middlewares.is_empty() {
+            return Some(self.f.as_ref()((request, path_args)));
+        }
+        let middlewares: Vec<Arc<dyn Middleware>> = outer.iter()
+            .chain(self.middlewares.iter())
+            .cloned()
+            .collect();
+        Some(Ok(run_middlewares(&middlewares, request, |request| {
+            self.f.as_ref()((request, path_args))
+        }, render)))
     }
 }
 
 
-// type EndPointsMap = HashMap<String, HashMap<Method<'static>, Route<'static>>>;
-// type EndPointsMap = HashMap<String, Vec<Route<'static>>>;
-/// {prefix -> routes}
-type EndPointsMap<'a> = HashMap<String, Vec<Box<Route<'a>>>>;
-/// {prefix -> routers}
-type RoutersMap<'a> = HashMap<String, Vec<Box<Router<'a>>>>;
-
-
 pub struct Router<'a> {
-    routers: RoutersMap<'a>,
-    endpoints: EndPointsMap<'a>,
+    /// in registration order, the tree refers to them by index
+    routes: Vec<Route<'a>>,
+    tree: Node,
+    middlewares: Vec<Arc<dyn Middleware>>,
 }
 
 impl<'a> Router<'a> {
     pub fn new() -> Self {
-        let routers = HashMap::<String, Vec<Box<Router>>>::new();
-        let endpoints = EndPointsMap::new();
         Self {
-            routers,
-            endpoints,
+            routes: vec![],
+            tree: Node::new(),
+            middlewares: vec![],
         }
     }
 
-    pub fn get<F>(&mut self, path: &'a str, f: F)
+    pub fn get<F>(&mut self, path: &'a str, f: F) -> &mut Operation
     where
-        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + 'static 
+        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + Sync + 'static 
     {
-        self.add_route(Box::new(Route::new(path, "GET", f)));
+        self.add_route(Route::new(path, "GET", f))
     }
 
-    pub fn post<F>(&mut self, path: &'a str, f: F)
+    pub fn post<F>(&mut self, path: &'a str, f: F) -> &mut Operation
     where
-        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + 'static 
+        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + Sync + 'static 
     {
-        self.add_route(Box::new(Route::new(path, "POST", f)));
+        self.add_route(Route::new(path, "POST", f))
     }
 
-    pub fn put<F>(&mut self, path: &'a str, f: F)
+    pub fn put<F>(&mut self, path: &'a str, f: F) -> &mut Operation
     where
-        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + 'static 
+        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + Sync + 'static 
     {
-        self.add_route(Box::new(Route::new(path, "PUT", f)));
+        self.add_route(Route::new(path, "PUT", f))
     }
 
-    pub fn patch<F>(&mut self, path: &'a str, f: F)
+    pub fn patch<F>(&mut self, path: &'a str, f: F) -> &mut Operation
     where
-        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + 'static 
+        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + Sync + 'static 
     {
-        self.add_route(Box::new(Route::new(path, "PATCH", f)));
+        self.add_route(Route::new(path, "PATCH", f))
     }
 
-    pub fn delete<F>(&mut self, path: &'a str, f: F)
+    pub fn delete<F>(&mut self, path: &'a str, f: F) -> &mut Operation
     where
-        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + 'static 
+        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + Sync + 'static 
     {
-        self.add_route(Box::new(Route::new(path, "DELETE", f)));
+        self.add_route(Route::new(path, "DELETE", f))
     }
 
-    pub fn option<F>(&mut self, path: &'a str, f: F)
+    pub fn option<F>(&mut self, path: &'a str, f: F) -> &mut Operation
     where
-        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + 'static 
+        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + Sync + 'static 
     {
-        self.add_route(Box::new(Route::new(path, "OPTION", f)));
+        self.add_route(Route::new(path, "OPTIONS", f))
     }
 
-    pub fn head<F>(&mut self, path: &'a str, f: F)
+    pub fn head<F>(&mut self, path: &'a str, f: F) -> &mut Operation
     where
-        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + 'static 
+        F: Fn((&Request, HashMap<String,String>)) -> ResponseResult + Send + Sync + 'static 
     {
-        self.add_route(Box::new(Route::new(path, "HEAD", f)));
+        self.add_route(Route::new(path, "HEAD", f))
     }
 
-    fn add_route(&mut self, route: Box<Route<'a>>) {
-        // TODO: FAIL: error[E0599]: the method `insert` exists for mutable reference `&mut HashMap<Method<'static>, Route<'static>>`, but its trait bounds were not satisfie
-        // let method = get_method_from_str(method).unwrap();
-        // let map = self.endpoints
-        //     .entry(String::from(route.path))
-        //     .or_insert(HashMap::<Method<'static>, Route>::new());
-        // map.insert(method, route);
-        //
-        let entry = self.endpoints
-            .entry(String::from(route.path))
-            .or_insert(vec![]);
-        entry.push(route);
+    /// Documentation of the route for the OpenAPI document
+    fn add_route(&mut self, route: Route<'a>) -> &mut Operation {
+        self.tree.insert(&path_segments(&route.path), self.routes.len());
+        self.routes.push(route);
+        &mut self.routes.last_mut().unwrap().doc
+    }
+
+    /// Wrap every route of this router, including the ones added later,
+    /// in `middleware`. Middlewares added first are the outer ones.
+    pub fn add_middleware<M>(&mut self, middleware: M)
+    where M: Middleware + 'static
+    {
+        self.middlewares.push(Arc::new(middleware));
     }
 
+    /// Move the routes of `router` into this one under `prefix`, they keep
+    /// the middlewares of `router` inside the ones of this router.
+    #[allow(clippy::boxed_local)]
     pub fn include_router(&mut self, prefix: &str, router: Box<Router<'a>>) {
         // validate prefix
         if prefix.len() > 0 && !prefix.starts_with("/") {
@@ -164,40 +367,142 @@ impl<'a> Router<'a> {
                 "prefix must be empty or starting with '/', got '{prefix}'"
             );
         }
-        let entry = self.routers
-            .entry(String::from(prefix))
-            .or_insert(vec![]);
-        entry.push(router);
-    }
-
-    pub fn route(&self, path: &str, request: &Request) -> Option<ResponseResult> {
-        // TODO: implemented in a very stupid way, try to optimized later
-        for (_path, routes) in self.endpoints.iter() {
-            println!("175: matching {} with {}", path, _path);
-            for route in routes.iter() {
-                if route.method != request.method { continue; }
-                if let Some(v) = route.execute(path, request) {
-                    return Some(v);
-                } else {
-                    continue;
+        for route in router.routes {
+            let path = format!("{prefix}{}", route.path);
+            let mut included = Route::from_callback(Cow::Owned(path), route.method, route.f);
+            included.middlewares = router.middlewares.iter()
+                .chain(route.middlewares.iter())
+                .cloned()
+                .collect();
+            included.doc = route.doc;
+            self.add_route(included);
+        }
+    }
+
+    /// Serve the files of `files` under `prefix`, e.g. `/static/css/app.css`.
+    /// The routes are left out of the OpenAPI document.
+    pub fn mount(&mut self, prefix: &str, files: StaticFiles) {
+        if !prefix.is_empty() && (!prefix.starts_with("/") || prefix.ends_with("/")) {
+            panic!(
+                "prefix must be empty or starting and not ending with '/', got '{prefix}'"
+            );
+        }
+        let files = Arc::new(files);
+        let mut paths = vec![format!("{prefix}/"), format!("{prefix}/{{path:path}}")];
+        if !prefix.is_empty() {
+            // redirected to "{prefix}/"
+            paths.push(String::from(prefix));
+        }
+        for path in paths {
+            let files = Arc::clone(&files);
+            let f: Callback = Box::new(move |(request, path_args)| {
+                let path = path_args.get("path").map_or("", |v| v.as_str());
+                files.serve(request, path)
+            });
+            self.add_route(Route::from_callback(Cow::Owned(path), METHOD::GET, f))
+                .hidden();
+        }
+    }
+
+    fn find(&self, method: &Method, path: &str) -> Option<&Route<'a>> {
+        let segments: Vec<&str> = path.split('/').collect();
+        let i = self.tree.find(&segments, &mut |i, catch_all| {
+            let route = &self.routes[i];
+            route.method == *method && (!catch_all || route.re.is_match(path))
+        })?;
+        Some(&self.routes[i])
+    }
+
+    /// Path pattern of the route matching the method and path, e.g.
+    /// `/users/{id:int}` for `GET /users/42`
+    pub fn route_path(&self, method: &Method, path: &str) -> Option<&str> {
+        Some(&self.find(method, path)?.path)
+    }
+
+    /// OpenAPI document of the routes, in registration order.
+    /// `/users/{id:int}` is written `/users/{id}` with an integer parameter.
+    pub fn openapi(&self, spec: &OpenApi) -> JsonValue {
+        let mut paths = serde_json::Map::new();
+        for route in self.routes.iter().filter(|r| !r.doc.hidden) {
+            let mut path = String::new();
+            let mut params = vec![];
+            for part in parse_path(&route.path) {
+                match part {
+                    PathPart::Static(s) => path.push_str(&s),
+                    PathPart::Param(name, converter) => {
+                        path.push_str(&format!("{{{name}}}"));
+                        params.push(Parameter {
+                            name,
+                            location: Location::Path,
+                            required: true,
+                            schema: converter.json_schema(),
+                            description: None,
+                        });
+                    },
                 }
             }
+            let item = paths.entry(path).or_insert_with(|| json!({}));
+            item[route.method.to_string().to_lowercase()] = route.doc.to_json(params);
         }
+        spec.document(JsonValue::Object(paths))
+    }
 
-        for (prefix, routers) in self.routers.iter() {
-            println!("187: matching {} with {}", path, prefix);
-            if !path.starts_with(prefix) { continue; }
-            let subpath = &path[prefix.len()..];
-            if subpath.len() != 0 && !subpath.starts_with("/") { continue; }
+    /// Methods allowed on the path, HEAD comes with GET and OPTIONS is
+    /// always answered. Empty if no route matches the path.
+    pub fn allowed_methods(&self, path: &str) -> Vec<String> {
+        let segments: Vec<&str> = path.split('/').collect();
+        let mut methods: Vec<String> = vec![];
+        self.tree.find(&segments, &mut |i, catch_all| {
+            let route = &self.routes[i];
+            let method = route.method.to_string();
+            if (!catch_all || route.re.is_match(path)) && !methods.contains(&method) {
+                methods.push(method);
+            }
+            false
+        });
+        if methods.is_empty() {
+            return methods;
+        }
+        let has = |methods: &Vec<String>, method: &str| methods.iter().any(|m| m == method);
+        if has(&methods, "GET") && !has(&methods, "HEAD") {
+            methods.push(String::from("HEAD"));
+        }
+        if !has(&methods, "OPTIONS") {
+            methods.push(String::from("OPTIONS"));
+        }
+        methods
+    }
 
-            for router in routers {
-                match router.route(subpath, request) {
-                    Some(resp) => return Some(resp),
-                    None => continue,
-                }
+    /// None if no route matches the path. A path matched with another
+    /// method gets a 405 error, or 204 with the allowed methods for OPTIONS.
+    /// HEAD falls back to the GET handler, the app drops the body. Errors
+    /// of routes with middlewares are rendered with `render`, so that their
+    /// after hooks see them.
+    pub fn route(&self, path: &str, request: &mut Request, render: &RenderError) -> Option<ResponseResult> {
+        if let Some(route) = self.find(&request.method, path) {
+            return route.execute(path, request, &self.middlewares, render);
+        }
+        if request.method == METHOD::HEAD {
+            if let Some(route) = self.find(&METHOD::GET, path) {
+                return route.execute(path, request, &self.middlewares, render);
             }
         }
-        None
+        let allowed = self.allowed_methods(path);
+        if allowed.is_empty() {
+            return None;
+        }
+        let allow = allowed.join(", ");
+        if request.method != METHOD::OPTIONS {
+            return Some(Err(
+                HttpError::new(405, "Method Not Allowed").with_header("Allow", &allow)
+            ));
+        }
+        Some(make_empty_response(204)
+            .map_err(HttpError::from)
+            .map(|v| {
+                let mut resp: Box<dyn MakeResponse> = Box::new(v);
+                resp.headers_mut().insert(String::from("Allow"), (allow, vec![]));
+                resp
+            }))
     }
 }
-
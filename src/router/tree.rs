@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+
+/// One segment of a route path, between two '/'
+#[derive(Clone, Debug)]
+pub enum Segment {
+    /// e.g. `users`
+    Static(String),
+    /// e.g. `{id:int}` or `{name}.{ext}`, matched by an anchored regex
+    Param {
+        pattern: String,
+        /// false for a plain `{name}`, which matches any segment
+        constrained: bool,
+    },
+    /// a segment with a `path` parameter, it matches the rest of the path
+    CatchAll,
+}
+
+
+struct ParamNode {
+    pattern: String,
+    re: Regex,
+    constrained: bool,
+    node: Node,
+}
+
+/// Prefix tree of route paths with one level per segment. The nodes hold
+/// indexes into the routes of the router.
+pub struct Node {
+    statics: HashMap<String, Node>,
+    /// constrained parameters first, then plain ones, in insertion order
+    params: Vec<ParamNode>,
+    /// routes with a `path` parameter from this node on, they are matched
+    /// against the whole path by the router
+    catch_all: Vec<usize>,
+    /// routes ending at this node
+    routes: Vec<usize>,
+}
+
+impl Node {
+    pub fn new() -> Self {
+        Self {
+            statics: HashMap::new(),
+            params: vec![],
+            catch_all: vec![],
+            routes: vec![],
+        }
+    }
+
+    pub fn insert(&mut self, segments: &[Segment], route: usize) {
+        let (segment, rest) = match segments.split_first() {
+            Some(v) => v,
+            None => {
+                self.routes.push(route);
+                return;
+            },
+        };
+        match segment {
+            Segment::Static(s) => {
+                self.statics
+                    .entry(s.clone())
+                    .or_default()
+                    .insert(rest, route);
+            },
+            Segment::Param { pattern, constrained } => {
+                let i = match self.params.iter().position(|p| &p.pattern == pattern) {
+                    Some(i) => i,
+                    None => {
+                        let param = ParamNode {
+                            pattern: pattern.clone(),
+                            re: Regex::new(pattern).unwrap(),
+                            constrained: *constrained,
+                            node: Node::new(),
+                        };
+                        // keep the constrained parameters before the plain ones
+                        let i = if *constrained {
+                            self.params.iter()
+                                .position(|p| !p.constrained)
+                                .unwrap_or(self.params.len())
+                        } else {
+                            self.params.len()
+                        };
+                        self.params.insert(i, param);
+                        i
+                    },
+                };
+                self.params[i].node.insert(rest, route);
+            },
+            Segment::CatchAll => self.catch_all.push(route),
+        }
+    }
+
+    /// Find the first route accepted by `accept(route, is_catch_all)`.
+    /// Static segments are tried before constrained parameters, then plain
+    /// parameters, then catch-alls; a dead end backtracks to the next one.
+    /// An `accept` that returns false visits every route matching the path.
+    pub fn find(&self, segments: &[&str], accept: &mut dyn FnMut(usize, bool) -> bool) -> Option<usize> {
+        let (segment, rest) = match segments.split_first() {
+            Some(v) => v,
+            None => return self.routes.iter().copied().find(|&i| accept(i, false)),
+        };
+        if let Some(node) = self.statics.get(*segment) {
+            if let Some(i) = node.find(rest, accept) {
+                return Some(i);
+            }
+        }
+        for param in self.params.iter() {
+            if !param.re.is_match(segment) { continue; }
+            if let Some(i) = param.node.find(rest, accept) {
+                return Some(i);
+            }
+        }
+        self.catch_all.iter().copied().find(|&i| accept(i, true))
+    }
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self::new()
+    }
+}
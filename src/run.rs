@@ -1,8 +1,15 @@
-use std::net::TcpListener;
-use std::sync::{Arc, Mutex};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
 
 use crate::app::App;
-use crate::thread_pool::ThreadPool;
+use crate::response::{make_text_response, MakeResponse};
+use crate::shutdown::Shutdown;
+use crate::thread_pool::{JobStatus, ThreadPool};
+
+#[cfg(target_os = "linux")]
+mod evented;
+#[cfg(target_os = "linux")]
+pub use evented::run_evented;
 
 
 fn get_listener(host: &str, port: usize) -> Result<TcpListener,String> {
@@ -19,15 +26,58 @@ fn get_listener(host: &str, port: usize) -> Result<TcpListener,String> {
 }
 
 
+fn watch_shutdown(app: &App, listener: &TcpListener) -> Shutdown {
+    let shutdown = app.shutdown_handle();
+    if let Ok(addr) = listener.local_addr() {
+        shutdown.set_wakeup(addr);
+    }
+    shutdown
+}
+
+
+fn new_pool(app: &App, threads: usize) -> ThreadPool {
+    let queue = app.queue();
+    ThreadPool::with_queue(threads, queue.capacity, queue.policy)
+}
+
+
+/// Answer a connection rejected by the full job queue or the shutdown
+fn reject_connection(stream: &TcpStream, app: &App) {
+    let mut resp: Box<dyn MakeResponse> = match make_text_response(
+        503, String::from("Service Unavailable"),
+    ) {
+        Ok(v) => Box::new(v),
+        Err(_) => return,
+    };
+    let headers = resp.headers_mut();
+    headers.insert(
+        String::from("Retry-After"),
+        (app.queue().retry_after.as_secs().to_string(), vec![]),
+    );
+    headers.insert(
+        String::from("Connection"),
+        (String::from("close"), vec![]),
+    );
+    let _ = resp.write(stream);
+    let _ = stream.shutdown(std::net::Shutdown::Write);
+}
+
+
 pub fn run(app: App, host: &str, port: usize) -> Result<(),String> {
     let listener = get_listener(host, port)?;
+    let shutdown = watch_shutdown(&app, &listener);
 
     for stream in listener.incoming() {
+        if shutdown.is_triggered() {
+            break;
+        }
         let stream = match stream {
             Ok(v) => v,
             Err(_) => continue,
         };
-        app.handle_connection(stream);
+        if let Err(e) = app.handle_connection(stream) {
+            println!("{e}");
+        }
     }
     Ok(())
 }
@@ -35,20 +85,52 @@ pub fn run(app: App, host: &str, port: usize) -> Result<(),String> {
 
 pub fn run_multithread(app: App<'static>, host: &str, port: usize, threads: usize) -> Result<(),String> {
     let listener = get_listener(host, port)?;
-    let pool = ThreadPool::new(threads);
-    let wrapped_app = Arc::new(Mutex::new(app));
+    let pool = new_pool(&app, threads);
+    let metrics = pool.metrics();
+    // routes are immutable once the server starts, workers share the app
+    // without locking so N workers really serve N connections at once
+    let app = Arc::new(app);
+    let shutdown = watch_shutdown(&app, &listener);
 
     for stream in listener.incoming() {
+        if shutdown.is_triggered() {
+            break;
+        }
         let stream = match stream {
             Ok(v) => v,
             Err(_) => continue,
         };
         
-        let app_cloned = Arc::clone(&wrapped_app);
-        pool.execute(move || {
-            let _app = app_cloned.lock().unwrap();
-            _app.handle_connection(stream);
+        let app = Arc::clone(&app);
+        let metrics = Arc::clone(&metrics);
+        let shutdown = shutdown.clone();
+        pool.submit(move |status| match status {
+            // still queued when the shutdown started, `drain` doesn't wait
+            // for it and its request may not arrive before the idle timeout
+            JobStatus::Run if shutdown.is_triggered() => {
+                reject_connection(&stream, &app);
+            },
+            JobStatus::Run => {
+                if let Err(e) = app.handle_connection(stream) {
+                    println!("{e}");
+                }
+            },
+            JobStatus::Rejected => {
+                println!("Job queue is full, reject connection; {metrics}");
+                reject_connection(&stream, &app);
+            },
         });
     }
+    // stop accepting, let in-flight requests finish and then join workers
+    drop(listener);
+    shutdown.drain();
+    drop(pool);
+    println!("{metrics}");
     Ok(())
 }
+
+
+#[cfg(not(target_os = "linux"))]
+pub fn run_evented(_app: App<'static>, _host: &str, _port: usize, _threads: usize) -> Result<(),String> {
+    Err(String::from("Evented mode requires epoll, it is only available on Linux"))
+}
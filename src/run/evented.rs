@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::app::App;
+use crate::epoll::{self, Epoll};
+use crate::thread_pool::{JobStatus, ThreadPool};
+
+use super::{get_listener, watch_shutdown, new_pool, reject_connection};
+
+
+/// State of a connection watched by the event loop
+struct Connection {
+    stream: Arc<TcpStream>,
+    /// number of requests served
+    served: usize,
+    last_active: Instant,
+    /// a worker is serving the connection, it's not watched by epoll
+    busy: bool,
+}
+
+type Connections = Arc<Mutex<HashMap<RawFd, Connection>>>;
+
+const CONNECTION_EVENTS: i32 = epoll::EPOLLIN | epoll::EPOLLRDHUP | epoll::EPOLLONESHOT;
+
+
+/// Serve with a readiness based event loop. Idle keep-alive connections are
+/// watched by epoll and only occupy a worker while a request is ready, so a
+/// few workers can hold thousands of long-lived connections.
+pub fn run_evented(app: App<'static>, host: &str, port: usize, threads: usize) -> Result<(),String> {
+    let listener = get_listener(host, port)?;
+    if let Err(e) = listener.set_nonblocking(true) {
+        return Err(format!("Fail to set listener non-blocking: {e}"));
+    }
+    let epoll = match Epoll::new() {
+        Ok(v) => Arc::new(v),
+        Err(e) => return Err(format!("Fail to create epoll: {e}")),
+    };
+    let listener_fd = listener.as_raw_fd();
+    if let Err(e) = epoll.add(listener_fd, epoll::EPOLLIN) {
+        return Err(format!("Fail to watch listener: {e}"));
+    }
+    let pool = new_pool(&app, threads);
+    let app = Arc::new(app);
+    let connections: Connections = Arc::new(Mutex::new(HashMap::new()));
+
+    let shutdown = watch_shutdown(&app, &listener);
+
+    while !shutdown.is_triggered() {
+        // wake up regularly to close idle connections
+        let events = match epoll.wait(1024, Some(Duration::from_secs(1))) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Fail to wait for events: {e}")),
+        };
+        for event in events {
+            if event.fd == listener_fd {
+                accept_connections(&listener, &epoll, &connections, app.keep_alive().timeout);
+            } else {
+                dispatch_connection(event.fd, &pool, &app, &epoll, &connections);
+            }
+        }
+        close_idle_connections(&connections, app.keep_alive().timeout);
+    }
+
+    // stop accepting, let in-flight requests finish and then join workers
+    let _ = epoll.delete(listener_fd);
+    drop(listener);
+    let deadline = Instant::now() + shutdown.grace();
+    loop {
+        close_idle_connections(&connections, Duration::ZERO);
+        if connections.lock().unwrap().is_empty() {
+            break;
+        }
+        if Instant::now() >= deadline {
+            println!("Closing connections after grace period");
+            for conn in connections.lock().unwrap().values() {
+                let _ = conn.stream.shutdown(std::net::Shutdown::Both);
+            }
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let metrics = pool.metrics();
+    drop(pool);
+    println!("{metrics}");
+    Ok(())
+}
+
+
+fn accept_connections(listener: &TcpListener, epoll: &Epoll, connections: &Connections, timeout: Duration) {
+    loop {
+        let stream = match listener.accept() {
+            Ok((v, _)) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return,
+            Err(_) => continue,
+        };
+        println!("Connection established");
+        // workers read with blocking calls, the timeout bounds a slow request
+        let _ = stream.set_read_timeout(Some(timeout));
+        let _ = stream.set_nodelay(true);
+        let fd = stream.as_raw_fd();
+        let mut connections = connections.lock().unwrap();
+        if epoll.add(fd, CONNECTION_EVENTS).is_err() {
+            continue;
+        }
+        connections.insert(fd, Connection {
+            stream: Arc::new(stream),
+            served: 0,
+            last_active: Instant::now(),
+            busy: false,
+        });
+    }
+}
+
+
+fn dispatch_connection(
+    fd: RawFd,
+    pool: &ThreadPool,
+    app: &Arc<App<'static>>,
+    epoll: &Arc<Epoll>,
+    connections: &Connections,
+) {
+    let stream = {
+        let mut connections = connections.lock().unwrap();
+        match connections.get_mut(&fd) {
+            Some(conn) if !conn.busy => {
+                conn.busy = true;
+                Arc::clone(&conn.stream)
+            },
+            _ => return,
+        }
+    };
+    let app = Arc::clone(app);
+    let epoll = Arc::clone(epoll);
+    let connections = Arc::clone(connections);
+    pool.submit(move |status| {
+        if let JobStatus::Rejected = status {
+            println!("Job queue is full, reject connection");
+            connections.lock().unwrap().remove(&fd);
+            reject_connection(&stream, &app);
+            return;
+        }
+        let mut buf_reader = BufReader::new(&*stream);
+        let mut kept = true;
+        // serve the ready request and the pipelined ones already buffered
+        loop {
+            match buf_reader.fill_buf() {
+                Ok(buf) if !buf.is_empty() => {},
+                _ => {
+                    kept = false;
+                    break;
+                },
+            }
+            let served = match connections.lock().unwrap().get_mut(&fd) {
+                Some(conn) => {
+                    conn.served += 1;
+                    conn.served
+                },
+                None => 0,
+            };
+            match app.handle_request(&mut buf_reader, &stream, served) {
+                Ok(true) => {},
+                Ok(false) => {
+                    kept = false;
+                    break;
+                },
+                Err(e) => {
+                    println!("{e}");
+                    kept = false;
+                    break;
+                },
+            }
+            if buf_reader.buffer().is_empty() {
+                break;
+            }
+        }
+        // hand the connection back to the event loop, or close it
+        let mut connections = connections.lock().unwrap();
+        if kept && epoll.modify(fd, CONNECTION_EVENTS).is_ok() {
+            if let Some(conn) = connections.get_mut(&fd) {
+                conn.busy = false;
+                conn.last_active = Instant::now();
+            }
+        } else {
+            connections.remove(&fd);
+        }
+    });
+}
+
+
+fn close_idle_connections(connections: &Connections, timeout: Duration) {
+    connections.lock().unwrap().retain(|_, conn| {
+        conn.busy || conn.last_active.elapsed() < timeout
+    });
+}
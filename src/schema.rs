@@ -3,3 +3,5 @@ pub use base::*;
 
 mod fields;
 pub use fields::*;
+
+pub use regex::Regex;
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use crate::request::content_type::RawDataType;
+use crate::json::{JsonValue, json};
 
 
 #[derive(Clone,Debug)]
@@ -7,6 +8,11 @@ pub enum Location {
     Path,
     Body,
     Query,
+    /// case-insensitive header name
+    Header,
+    Cookie,
+    /// an entry of a multipart or urlencoded form body
+    Form,
     Other(String),
     None,
 }
@@ -17,6 +23,9 @@ impl Location {
             Location::Path => String::from("path"),
             Location::Body => String::from("body"),
             Location::Query => String::from("query"),
+            Location::Header => String::from("header"),
+            Location::Cookie => String::from("cookie"),
+            Location::Form => String::from("form"),
             Location::Other(v) => v.to_string(),
             _ => String::from("")
         }
@@ -32,6 +41,9 @@ impl Location {
             Location::Query => match value {
                 Location::Query => false, _ => true,
             },
+            Location::Header => !matches!(value, Location::Header),
+            Location::Cookie => !matches!(value, Location::Cookie),
+            Location::Form => !matches!(value, Location::Form),
             Location::Other(v) => match value {
                 Location::Other(_v) => v != _v, _ => true,
             },
@@ -49,6 +61,21 @@ pub struct ValidationError {
     pub reason: String,
 }
 
+impl ValidationError {
+    /// FastAPI style error item: {"loc": ["query", "page"], "msg": "..."}
+    pub fn to_json(&self) -> JsonValue {
+        let mut loc: Vec<String> = vec![];
+        let location = self.location.to_string();
+        if !location.is_empty() {
+            loc.push(location);
+        }
+        if let Some(field) = self.field.as_ref().filter(|v| !v.is_empty()) {
+            loc.push(field.clone());
+        }
+        json!({"loc": loc, "msg": self.reason})
+    }
+}
+
 pub type ValidationErrors = Vec<ValidationError>;
 pub type ValidationResult<T> = Result<T, ValidationErrors>;
 
@@ -92,6 +119,8 @@ pub trait FieldValidate {
     fn required(&self) -> bool { self.common().required }
     fn location(&self) -> Location { self.common().location.clone() }
     fn field(&self) -> Option<String> { self.common().field.clone() }
+    /// JSON schema of the accepted values, e.g. for the OpenAPI document
+    fn json_schema(&self) -> JsonValue { json!({}) }
 
     fn _validate_pre(&self, value: RawDataType) -> ValidationResult<Self::Type>;
     fn validate_pre(&self, value: RawDataType) -> ValidationResult<Self::Type> {
@@ -112,6 +141,11 @@ pub trait FieldValidate {
     fn validate(&self, value: RawDataType) -> ValidationResult<Self::Type> {
         Ok(self.validate_post(self.validate_pre(value)?)?)
     }
+    /// Validate every value of a repeated name, e.g. `tags=a&tags=b` in a
+    /// form. Fields of one value take the last one.
+    fn validate_all(&self, mut values: Vec<RawDataType>) -> ValidationResult<Self::Type> {
+        self.validate(values.pop().unwrap_or(RawDataType::None))
+    }
     fn parse(&mut self, value: RawDataType) -> ValidationResult<()> {
         let v = self.validate(value)?;
         self.common_mut().set_val(v);
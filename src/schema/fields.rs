@@ -16,6 +16,41 @@ use crate::json;
 use crate::request::content_type::{RawDataType, FileCursor};
 
 
+/// Error of a raw value of another kind, e.g. a file given to a text field
+fn unexpected_type<T>(location: Location, expected: &str) -> ValidationResult<T> {
+    Err(vec![ValidationError {
+        location,
+        field: None,
+        reason: format!("value should be {expected}"),
+    }])
+}
+
+/// JSON number of a constraint, None if it has none, e.g. NaN
+fn json_number<T: std::fmt::Display>(value: &T) -> Option<json::JsonValue> {
+    match json::parse(&value.to_string()) {
+        Ok(v @ json::JsonValue::Number(_)) => Some(v),
+        _ => None,
+    }
+}
+
+/// Schema of an `Integer` or a `Float`
+fn number_schema<T: std::fmt::Display>(
+    kind: &str,
+    default: &Option<T>,
+    bounds: [(&str, &Option<T>); 4],
+) -> json::JsonValue {
+    let mut schema = json::json!({"type": kind});
+    for (key, bound) in bounds {
+        if let Some(v) = bound.as_ref().and_then(json_number) {
+            schema[key] = v;
+        }
+    }
+    if let Some(v) = default.as_ref().and_then(json_number) {
+        schema["default"] = v;
+    }
+    schema
+}
+
 fn some_if<T,F>(some: &Option<T>, f: F) -> bool
 where F: Fn(&T) -> bool {
     match some {
@@ -52,6 +87,18 @@ where T: num::Integer + std::str::FromStr + std::fmt::Display + std::fmt::Debug
 
     fn common(&self) -> &Common<Self::Type> { &self.common }
     fn common_mut(&mut self) -> &mut Common<Self::Type> { &mut self.common }
+    fn json_schema(&self) -> json::JsonValue {
+        let mut schema = number_schema("integer", self.default(), [
+            ("exclusiveMinimum", &self.gt),
+            ("minimum", &self.ge),
+            ("exclusiveMaximum", &self.lt),
+            ("maximum", &self.le),
+        ]);
+        if let Some(choice) = &self.choice {
+            schema["enum"] = choice.iter().filter_map(json_number).collect();
+        }
+        schema
+    }
 
     fn _validate_pre(&self, value: RawDataType) -> ValidationResult<Self::Type> {
         if let RawDataType::Text(value) = value {
@@ -67,7 +114,7 @@ where T: num::Integer + std::str::FromStr + std::fmt::Display + std::fmt::Debug
                 }]),
             }
         } else {
-            panic!("Raw input type should be RawDataType::Text");
+            unexpected_type(self.location(), "text")
         }
     }
     fn _validate_post(&self, errs: &mut ValidationErrors, value: &Self::Type) {
@@ -148,6 +195,14 @@ where T: num::Float + std::str::FromStr + std::fmt::Display + std::fmt::Debug +
 
     fn common(&self) -> &Common<Self::Type> { &self.common }
     fn common_mut(&mut self) -> &mut Common<Self::Type> { &mut self.common }
+    fn json_schema(&self) -> json::JsonValue {
+        number_schema("number", self.default(), [
+            ("exclusiveMinimum", &self.gt),
+            ("minimum", &self.ge),
+            ("exclusiveMaximum", &self.lt),
+            ("maximum", &self.le),
+        ])
+    }
 
     fn _validate_pre(&self, value: RawDataType) -> ValidationResult<Self::Type> {
         if let RawDataType::Text(value) = value {
@@ -163,7 +218,7 @@ where T: num::Float + std::str::FromStr + std::fmt::Display + std::fmt::Debug +
                 }]),
             }
         } else {
-            panic!("Raw input type should be RawDataType::Text");
+            unexpected_type(self.location(), "text")
         }
     }
     fn _validate_post(&self, errs: &mut ValidationErrors, value: &Self::Type) {
@@ -232,16 +287,36 @@ impl FieldValidate for Text {
 
     fn common(&self) -> &Common<Self::Type> { &self.common }
     fn common_mut(&mut self) -> &mut Common<Self::Type> { &mut self.common }
+    fn json_schema(&self) -> json::JsonValue {
+        let mut schema = json::json!({"type": "string"});
+        if let Some(min_len) = self.min_len {
+            schema["minLength"] = json::json!(min_len);
+        }
+        if let Some(max_len) = self.max_len {
+            schema["maxLength"] = json::json!(max_len);
+        }
+        if let Some(pattern) = &self.pattern {
+            schema["pattern"] = json::json!(pattern.as_str());
+        }
+        if let Some(choice) = &self.choice {
+            schema["enum"] = json::json!(choice);
+        }
+        if let Some(default) = self.default() {
+            schema["default"] = json::json!(default);
+        }
+        schema
+    }
 
     fn _validate_pre(&self, value: RawDataType) -> ValidationResult<Self::Type> {
         if let RawDataType::Text(value) = value {
             Ok(value.to_string())
         } else {
-            panic!("Raw input type should be RawDataType::Text");
+            unexpected_type(self.location(), "text")
         }
     }
     fn _validate_post(&self, errs: &mut ValidationErrors, value: &Self::Type) {
-        if !some_if(&self.min_len, |min_len| value.len() >= *min_len) {
+        // counted in characters like "minLength" of JSON schema
+        if !some_if(&self.min_len, |min_len| value.chars().count() >= *min_len) {
             errs.push(ValidationError {
                 location: self.location(),
                 field: None,
@@ -251,7 +326,7 @@ impl FieldValidate for Text {
                 ),
             });
         }
-        if !some_if(&self.max_len, |max_len| value.len() <= *max_len) {
+        if !some_if(&self.max_len, |max_len| value.chars().count() <= *max_len) {
             errs.push(ValidationError {
                 location: self.location(),
                 field: None,
@@ -261,7 +336,7 @@ impl FieldValidate for Text {
                 ),
             });
         }
-        if !some_if(&self.pattern, |pattern| todo!() ) { // TODO: 
+        if !some_if(&self.pattern, |pattern| pattern.is_match(value)) {
             errs.push(ValidationError {
                 location: self.location(),
                 field: None,
@@ -300,6 +375,13 @@ impl FieldValidate for Bool {
 
     fn common(&self) -> &Common<Self::Type> { &self.common }
     fn common_mut(&mut self) -> &mut Common<Self::Type> { &mut self.common }
+    fn json_schema(&self) -> json::JsonValue {
+        let mut schema = json::json!({"type": "boolean"});
+        if let Some(default) = self.default() {
+            schema["default"] = json::json!(default);
+        }
+        schema
+    }
 
     fn _validate_pre(&self, value: RawDataType) -> ValidationResult<Self::Type> {
         if let RawDataType::Text(value) = value {
@@ -313,7 +395,7 @@ impl FieldValidate for Bool {
                 }]),
             }
         } else {
-            panic!("Raw input type should be RawDataType::Text");
+            unexpected_type(self.location(), "text")
         }
     }
 }
@@ -337,6 +419,9 @@ impl FieldValidate for AnyJson {
 
     fn common(&self) -> &Common<Self::Type> { &self.common }
     fn common_mut(&mut self) -> &mut Common<Self::Type> { &mut self.common }
+    fn json_schema(&self) -> json::JsonValue {
+        self.schema.clone().unwrap_or_else(|| json::json!({}))
+    }
 
     fn _validate_pre(&self, value: RawDataType) -> ValidationResult<Self::Type> {
         if let RawDataType::Text(value) = value {
@@ -349,7 +434,7 @@ impl FieldValidate for AnyJson {
                 }])
             }
         } else {
-            panic!("Raw input type should be RawDataType::Text");
+            unexpected_type(self.location(), "text")
         }
     }
     fn _validate_post(&self, errs: &mut ValidationErrors, value: &Self::Type) {
@@ -395,12 +480,16 @@ impl FieldValidate for File {
 
     fn common(&self) -> &Common<Self::Type> { &self.common }
     fn common_mut(&mut self) -> &mut Common<Self::Type> { &mut self.common }
+    fn json_schema(&self) -> json::JsonValue {
+        // the allowed extensions of the file name can't be expressed
+        json::json!({"type": "string", "format": "binary"})
+    }
 
     fn _validate_pre(&self, value: RawDataType) -> ValidationResult<Self::Type> {
         if let RawDataType::File(value) = value {
             Ok(value.clone()) // TODO: better way that don't clone
         } else {
-            panic!("Raw input type should be RawDataType::Binary");
+            unexpected_type(self.location(), "a file")
         }
     }
     fn _validate_post(&self, errs: &mut ValidationErrors, value: &Self::Type) {
@@ -434,12 +523,13 @@ impl FieldValidate for Binary {
 
     fn common(&self) -> &Common<Self::Type> { &self.common }
     fn common_mut(&mut self) -> &mut Common<Self::Type> { &mut self.common }
+    fn json_schema(&self) -> json::JsonValue { json::json!({"type": "string", "format": "binary"}) }
 
     fn _validate_pre(&self, value: RawDataType) -> ValidationResult<Self::Type> {
         if let RawDataType::Binary(value) = value {
             Ok(value.to_vec())
         } else {
-            panic!("Raw input type should be RawDataType::Binary");
+            unexpected_type(self.location(), "binary data")
         }
     }
 }
@@ -466,6 +556,13 @@ where T: FieldValidate + HasDefault
         }
     }
 }
+impl<T> HasDefault for Array<T>
+where T: FieldValidate + HasDefault
+{
+    fn new() -> Self {
+        Array::new()
+    }
+}
 impl<T> FieldValidate for Array<T>
 where T: FieldValidate
 {
@@ -473,6 +570,16 @@ where T: FieldValidate
 
     fn common(&self) -> &Common<Self::Type> { &self.common }
     fn common_mut(&mut self) -> &mut Common<Self::Type> { &mut self.common }
+    fn json_schema(&self) -> json::JsonValue {
+        let mut schema = json::json!({"type": "array", "items": self.elem_field.json_schema()});
+        if let Some(min_len) = self.min_len {
+            schema["minItems"] = json::json!(min_len);
+        }
+        if let Some(max_len) = self.max_len {
+            schema["maxItems"] = json::json!(max_len);
+        }
+        schema
+    }
 
     fn _validate_pre(&self, value: RawDataType) -> ValidationResult<Self::Type> {
         if let RawDataType::Text(value) = value {
@@ -489,16 +596,7 @@ where T: FieldValidate
                             //
                             match self.elem_field.validate(RawDataType::Text(&s)) {
                                 Ok(v) => res.push(v),
-                                Err(_errs) => {
-                                    for mut _err in _errs {
-                                        let field = Some(match _err.field {
-                                            Some(_field) => format!("{i}.{_field}"),
-                                            None => i.to_string(),
-                                        });
-                                        _err.field = field;
-                                        errs.push(_err);
-                                    }
-                                }
+                                Err(_errs) => push_elem_errors(&mut errs, i, _errs),
                             }
                         } else {
                             errs.push(ValidationError {
@@ -524,8 +622,30 @@ where T: FieldValidate
                 }]),
             }
         } else {
-            panic!();
+            unexpected_type(self.location(), "text")
+        }
+    }
+    /// One value is the whole array as JSON text, e.g. `tags=["a","b"]`,
+    /// unless it is not an array; several values are the elements, e.g.
+    /// `tags=a&tags=b`
+    fn validate_all(&self, mut values: Vec<RawDataType>) -> ValidationResult<Self::Type> {
+        if let [RawDataType::Text(value)] = values.as_slice() {
+            if let Ok(json::JsonValue::Array(_)) = json::parse(value) {
+                return self.validate(values.pop().unwrap());
+            }
+        }
+        let mut res = Self::Type::new();
+        let mut errs = ValidationErrors::new();
+        for (i, value) in values.into_iter().enumerate() {
+            match self.elem_field.validate(value) {
+                Ok(v) => res.push(v),
+                Err(_errs) => push_elem_errors(&mut errs, i, _errs),
+            }
+        }
+        if !errs.is_empty() {
+            return Err(errs);
         }
+        self.validate_post(res)
     }
     fn _validate_post(&self, errs: &mut ValidationErrors, value: &Self::Type) {
         if !some_if(&self.min_len, |min_len| value.len() >= *min_len) {
@@ -538,12 +658,12 @@ where T: FieldValidate
                 ),
             });
         }
-        if !some_if(&self.max_len, |max_len| value.len() >= *max_len) {
+        if !some_if(&self.max_len, |max_len| value.len() <= *max_len) {
             errs.push(ValidationError {
                 location: self.location(),
                 field: None,
                 reason: format!(
-                    "array length should greater equal than {}",
+                    "array length should less equal than {}",
                     self.max_len.unwrap()
                 ),
             });
@@ -552,6 +672,18 @@ where T: FieldValidate
 }
 
 
+/// Errors of the `i`th element, the field is prefixed with its index
+fn push_elem_errors(errs: &mut ValidationErrors, i: usize, elem_errs: ValidationErrors) {
+    for mut err in elem_errs {
+        err.field = Some(match err.field {
+            Some(field) => format!("{i}.{field}"),
+            None => i.to_string(),
+        });
+        errs.push(err);
+    }
+}
+
+
 #[derive(Clone)]
 pub struct Mapping<T>
 where T: FieldValidate
@@ -573,6 +705,13 @@ where T: FieldValidate + HasDefault
         }
     }
 }
+impl<T> HasDefault for Mapping<T>
+where T: FieldValidate + HasDefault
+{
+    fn new() -> Self {
+        Mapping::new()
+    }
+}
 impl<T> FieldValidate for Mapping<T>
 where T: FieldValidate
 {
@@ -580,6 +719,19 @@ where T: FieldValidate
 
     fn common(&self) -> &Common<Self::Type> { &self.common }
     fn common_mut(&mut self) -> &mut Common<Self::Type> { &mut self.common }
+    fn json_schema(&self) -> json::JsonValue {
+        let mut schema = json::json!({
+            "type": "object",
+            "additionalProperties": self.elem_field.json_schema(),
+        });
+        if let Some(min_len) = self.min_len {
+            schema["minProperties"] = json::json!(min_len);
+        }
+        if let Some(max_len) = self.max_len {
+            schema["maxProperties"] = json::json!(max_len);
+        }
+        schema
+    }
 
     fn _validate_pre(&self, value: RawDataType) -> ValidationResult<Self::Type> {
         match value {
@@ -634,7 +786,7 @@ where T: FieldValidate
                     }]),
                 }
             },
-            _ => panic!("Raw input type should be RawDataType::Text"),
+            _ => unexpected_type(self.location(), "text"),
             
         }
     }
@@ -649,15 +801,42 @@ where T: FieldValidate
                 ),
             });
         }
-        if !some_if(&self.max_len, |max_len| value.len() >= *max_len) {
+        if !some_if(&self.max_len, |max_len| value.len() <= *max_len) {
             errs.push(ValidationError {
                 location: self.location(),
                 field: None,
                 reason: format!(
-                    "array length should greater equal than {}",
+                    "array length should less equal than {}",
                     self.max_len.unwrap()
                 ),
             });
         }
     }
 }
+
+
+/// Validator of a type, used by `#[derive(RequestModel)]`
+pub trait HasValidator: Sized {
+    type Field: FieldValidate<Type = Self> + HasDefault;
+}
+
+macro_rules! has_validator {
+    ($field:ty => $($t:ty),*) => {
+        $(impl HasValidator for $t { type Field = $field; })*
+    };
+}
+
+has_validator!(Integer<Self> => i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+has_validator!(Float<Self> => f32, f64);
+has_validator!(Text => String);
+has_validator!(Bool => bool);
+has_validator!(AnyJson => json::JsonValue);
+has_validator!(File => FileCursor);
+
+impl<T: HasValidator> HasValidator for Vec<T> {
+    type Field = Array<T::Field>;
+}
+
+impl<T: HasValidator> HasValidator for HashMap<String, T> {
+    type Field = Mapping<T::Field>;
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::net::{Shutdown as SocketShutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+
+struct TrackedConnection {
+    stream: TcpStream,
+    /// waiting for the next request of a keep-alive connection
+    idle: bool,
+}
+
+struct ShutdownInner {
+    triggered: AtomicBool,
+    /// time given to in-flight requests before their connections are closed
+    grace: Duration,
+    /// address of the listener, connecting to it wakes up a blocking accept
+    wakeup: Mutex<Option<SocketAddr>>,
+    connections: Mutex<HashMap<usize, TrackedConnection>>,
+    next_id: AtomicUsize,
+}
+
+
+/// Handle to stop a running server gracefully. Clones share the same state.
+///
+/// After `trigger` the run loops stop accepting, idle keep-alive connections
+/// are closed, in-flight requests are answered with "Connection: close" and
+/// connections still open after the grace period are closed by force.
+#[derive(Clone)]
+pub struct Shutdown {
+    inner: Arc<ShutdownInner>,
+}
+
+impl Shutdown {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            inner: Arc::new(ShutdownInner {
+                triggered: AtomicBool::new(false),
+                grace,
+                wakeup: Mutex::new(None),
+                connections: Mutex::new(HashMap::new()),
+                next_id: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    pub fn grace(&self) -> Duration {
+        self.inner.grace
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.inner.triggered.load(Ordering::SeqCst)
+    }
+
+    pub fn trigger(&self) {
+        if self.inner.triggered.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        println!("Shutting down ...");
+        // wake up the accept loop
+        let wakeup = *self.inner.wakeup.lock().unwrap();
+        if let Some(addr) = wakeup {
+            let _ = TcpStream::connect_timeout(&addr, Duration::from_millis(100));
+        }
+        self.close_idle();
+    }
+
+    /// Set the listener address used to wake up a blocking accept
+    pub fn set_wakeup(&self, addr: SocketAddr) {
+        *self.inner.wakeup.lock().unwrap() = Some(addr);
+    }
+
+    /// Track an open connection until the returned guard is dropped
+    pub fn track(&self, stream: &TcpStream) -> ConnectionGuard {
+        let id = self.inner.next_id.fetch_add(1, Ordering::SeqCst);
+        if let Ok(stream) = stream.try_clone() {
+            self.inner.connections.lock().unwrap().insert(id, TrackedConnection {
+                stream,
+                idle: false,
+            });
+        }
+        ConnectionGuard { shutdown: self.clone(), id }
+    }
+
+    pub fn open_connections(&self) -> usize {
+        self.inner.connections.lock().unwrap().len()
+    }
+
+    /// Close connections waiting for their next request
+    pub fn close_idle(&self) {
+        for conn in self.inner.connections.lock().unwrap().values() {
+            if conn.idle {
+                let _ = conn.stream.shutdown(SocketShutdown::Read);
+            }
+        }
+    }
+
+    /// Close every tracked connection, used when the grace period is over
+    pub fn close_all(&self) {
+        for conn in self.inner.connections.lock().unwrap().values() {
+            let _ = conn.stream.shutdown(SocketShutdown::Both);
+        }
+    }
+
+    /// Wait until the tracked connections are closed or the grace period is
+    /// over, then close the rest.
+    pub fn drain(&self) {
+        let deadline = Instant::now() + self.grace();
+        while self.open_connections() > 0 && Instant::now() < deadline {
+            // a connection may turn idle after the last call
+            self.close_idle();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let remaining = self.open_connections();
+        if remaining > 0 {
+            println!("Closing {remaining} connections after grace period");
+            self.close_all();
+        }
+    }
+}
+
+
+/// Removes the connection from the tracked ones when dropped
+pub struct ConnectionGuard {
+    shutdown: Shutdown,
+    id: usize,
+}
+
+impl ConnectionGuard {
+    pub fn set_idle(&self, idle: bool) {
+        if let Some(conn) = self.shutdown.inner.connections.lock().unwrap().get_mut(&self.id) {
+            conn.idle = idle;
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.shutdown.inner.connections.lock().unwrap().remove(&self.id);
+    }
+}
+
+
+static SIGNALED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_signal(_: libc::c_int) {
+    // only async-signal-safe work here, a watcher thread does the rest
+    SIGNALED.store(true, Ordering::SeqCst);
+}
+
+/// Trigger `shutdown` on SIGINT or SIGTERM
+#[cfg(unix)]
+pub fn trigger_on_signals(shutdown: Shutdown) {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+    std::thread::spawn(move || {
+        while !SIGNALED.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        shutdown.trigger();
+    });
+}
+
+#[cfg(not(unix))]
+pub fn trigger_on_signals(_shutdown: Shutdown) {
+}
@@ -0,0 +1,408 @@
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::iter::once;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::compression::{Encoding, add_vary, negotiate_encoding};
+use crate::conditional::{
+    Precondition,
+    evaluate_preconditions,
+    if_range_matches,
+    make_not_modified_response,
+    make_weak_etag,
+};
+use crate::error::HttpError;
+use crate::http::{
+    self,
+    ByteRanges,
+    METHOD,
+    format_http_date,
+    parse_range,
+};
+use crate::request::Request;
+use crate::response::{
+    Body,
+    MakeHtmlContent,
+    MakeResponse,
+    Response,
+    make_empty_response,
+    make_stream_response,
+};
+use crate::router::ResponseResult;
+
+
+/// MIME type of a file from its extension, "application/octet-stream" if
+/// it is unknown
+pub fn guess_mime_type(path: &Path) -> &'static str {
+    let ext = match path.extension().and_then(|v| v.to_str()) {
+        Some(v) => v.to_ascii_lowercase(),
+        None => return "application/octet-stream",
+    };
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "xml" => "application/xml",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Decode the %XX escapes of a path segment
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Escape a file name for a link of the directory listing
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(b as char);
+            },
+            _ => encoded.push_str(&format!("%{b:02X}")),
+        }
+    }
+    encoded
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+
+/// Boundary of a "multipart/byteranges" body, unique in this process
+fn make_boundary() -> String {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    format!("{:x}{:x}", nanos, COUNT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Stream `file`, or the parts asked by the "Range" header of a GET:
+///   one range       206 with "Content-Range"
+///   several ranges  206 with a "multipart/byteranges" body
+///   none in file    416 with "Content-Range: bytes */<length>"
+/// The file has a weak "ETag" from its modification time and size, and the
+/// conditional headers are answered with 304 or 412 before the ranges.
+/// "If-Range" not matching the file gets the whole file.
+pub fn make_file_response(request: &Request, mut file: fs::File, content_type: &str) -> ResponseResult {
+    let metadata = match file.metadata() {
+        Ok(v) => v,
+        Err(e) => return Err(HttpError::from(format!("Fail to read file metadata: {e}"))),
+    };
+    let len = metadata.len();
+    let modified = metadata.modified().ok();
+    let mut headers = http::Headers::new();
+    headers.insert(String::from("Accept-Ranges"), (String::from("bytes"), vec![]));
+    let etag = modified.map(|v| make_weak_etag(v, len));
+    if let Some(modified) = modified {
+        headers.insert(String::from("Last-Modified"), (format_http_date(modified), vec![]));
+    }
+    if let Some(etag) = &etag {
+        headers.insert(String::from("ETag"), (etag.clone(), vec![]));
+    }
+    match evaluate_preconditions(request, etag.as_deref(), modified) {
+        Precondition::Proceed => {},
+        Precondition::NotModified => return Ok(make_not_modified_response(&headers)?),
+        Precondition::Failed => return Err(HttpError::new(412, "Precondition Failed")),
+    }
+
+    let ranges = match request.header("Range") {
+        // other methods ignore "Range"
+        Some(range) if request.method == METHOD::GET
+            && if_range_matches(request, etag.as_deref(), modified) => {
+            parse_range(range, len)
+        },
+        _ => ByteRanges::Full,
+    };
+    let mut resp: Box<dyn MakeResponse> = match ranges {
+        ByteRanges::Full => Box::new(make_stream_response(
+            200,
+            content_type,
+            Some(len as usize),
+            Body::from_reader(file),
+        )?),
+        ByteRanges::Unsatisfiable => {
+            return Err(HttpError::new(416, "Range Not Satisfiable")
+                .with_header("Content-Range", &format!("bytes */{len}")));
+        },
+        ByteRanges::Partial(ranges) if ranges.len() == 1 => {
+            let (first, last) = ranges[0];
+            if let Err(e) = file.seek(SeekFrom::Start(first)) {
+                return Err(HttpError::from(format!("Fail to seek file: {e}")));
+            }
+            headers.insert(
+                String::from("Content-Range"),
+                (format!("bytes {first}-{last}/{len}"), vec![]),
+            );
+            Box::new(make_stream_response(
+                206,
+                content_type,
+                Some((last - first + 1) as usize),
+                Body::from_reader(file.take(last - first + 1)),
+            )?)
+        },
+        ByteRanges::Partial(ranges) => {
+            let boundary = make_boundary();
+            let parts: Vec<(Vec<u8>, u64, u64)> = ranges.iter()
+                .enumerate()
+                .map(|(i, (first, last))| {
+                    let head = format!(
+                        "{}--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: bytes {first}-{last}/{len}\r\n\r\n",
+                        if i == 0 { "" } else { "\r\n" },
+                    );
+                    (head.into_bytes(), *first, last - first + 1)
+                })
+                .collect();
+            let trailer = format!("\r\n--{boundary}--\r\n").into_bytes();
+            let body_len = parts.iter().map(|(head, _, n)| head.len() + *n as usize).sum::<usize>()
+                + trailer.len();
+            // the parts are read one after the other from the same file
+            let chunks = parts.into_iter()
+                .flat_map(move |(head, first, n)| {
+                    let part = file.try_clone().and_then(|mut f| {
+                        f.seek(SeekFrom::Start(first))?;
+                        Ok(f)
+                    });
+                    let body = match part {
+                        Ok(f) => Body::from_reader(f.take(n)),
+                        Err(e) => Body::Stream(Box::new(once(Err(e)))),
+                    };
+                    once(Ok(head)).chain(body.into_chunks())
+                })
+                .chain(once(Ok(trailer)));
+            Box::new(make_stream_response(
+                206,
+                &format!("multipart/byteranges; boundary={boundary}"),
+                Some(body_len),
+                Body::Stream(Box::new(chunks)),
+            )?)
+        },
+    };
+    resp.headers_mut().extend(headers);
+    Ok(resp)
+}
+
+
+/// Files of a directory served under a prefix of a `Router`, see
+/// `Router::mount`:
+///
+///   router.mount("/static", StaticFiles::new("static")?);
+///
+/// Files are streamed with the MIME type of their extension. Paths leaving
+/// the root, e.g. with `..` or a symlink, are not found.
+pub struct StaticFiles {
+    pub root: PathBuf,
+    /// file served for a directory, e.g. "index.html"
+    pub index: Option<String>,
+    /// list the content of the directories without an index file
+    pub listing: bool,
+    /// serve the files and directories whose name starts with '.'
+    pub hidden: bool,
+    /// serve "file.gz", when it exists, for "file" to the clients which
+    /// accept gzip
+    pub precompressed: bool,
+}
+
+impl StaticFiles {
+    pub fn new(root: &str) -> Result<Self, String> {
+        let root = PathBuf::from(root);
+        if !root.is_dir() {
+            return Err(format!("{} is not a directory", root.display()));
+        }
+        Ok(Self {
+            root,
+            index: Some(String::from("index.html")),
+            listing: false,
+            hidden: false,
+            precompressed: false,
+        })
+    }
+
+    /// Local path of `path`, relative to the root and percent-encoded as
+    /// in the request. None if it is not allowed.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let mut resolved = self.root.clone();
+        for segment in path.split('/') {
+            let segment = percent_decode(segment)?;
+            if segment.is_empty() || segment == "." {
+                continue;
+            }
+            if segment == ".."
+                || segment.contains(['/', '\\', '\0'])
+                || (!self.hidden && segment.starts_with('.')) {
+                return None;
+            }
+            resolved.push(segment);
+        }
+        // symlinks may point outside of the root
+        let canonical = fs::canonicalize(&resolved).ok()?;
+        if !canonical.starts_with(fs::canonicalize(&self.root).ok()?) {
+            return None;
+        }
+        Some(resolved)
+    }
+
+    /// Respond to `request` with the file at `path` under the root
+    pub fn serve(&self, request: &Request, path: &str) -> ResponseResult {
+        let local = match self.resolve(path) {
+            Some(v) => v,
+            None => return Err(HttpError::not_found()),
+        };
+        if !local.is_dir() {
+            return self.serve_file(request, &local);
+        }
+        // relative links of the page need the trailing '/'
+        if !request.path.ends_with('/') {
+            let mut resp: Box<dyn MakeResponse> = Box::new(make_empty_response(301)?);
+            resp.headers_mut().insert(
+                String::from("Location"),
+                (format!("{}/", request.path), vec![]),
+            );
+            return Ok(resp);
+        }
+        if let Some(index) = &self.index {
+            let index = local.join(index);
+            if index.is_file() {
+                return self.serve_file(request, &index);
+            }
+        }
+        if self.listing {
+            return self.list_dir(&request.path, &local);
+        }
+        Err(HttpError::not_found())
+    }
+
+    fn serve_file(&self, request: &Request, local: &Path) -> ResponseResult {
+        let gzip = match self.precompressed {
+            true => self.gzip_sibling(local),
+            false => None,
+        };
+        let accepts_gzip = request.header("Accept-Encoding")
+            .and_then(|v| negotiate_encoding(v, &[Encoding::Gzip]))
+            .is_some();
+        let (path, encoded) = match &gzip {
+            Some(gzip) if accepts_gzip => (gzip.as_path(), true),
+            _ => (local, false),
+        };
+        let file = match fs::File::open(path) {
+            Ok(v) => v,
+            Err(_) => return Err(HttpError::not_found()),
+        };
+        if !file.metadata().is_ok_and(|v| v.is_file()) {
+            return Err(HttpError::not_found());
+        }
+        let mut resp = make_file_response(request, file, guess_mime_type(local))?;
+        if gzip.is_some() {
+            add_vary(resp.headers_mut(), "Accept-Encoding");
+        }
+        if encoded && resp.status().code != 304 {
+            resp.headers_mut().insert(
+                String::from("Content-Encoding"),
+                (String::from(Encoding::Gzip.as_str()), vec![]),
+            );
+        }
+        Ok(resp)
+    }
+
+    /// "file.gz" next to `local`, if it is a file under the root
+    fn gzip_sibling(&self, local: &Path) -> Option<PathBuf> {
+        let mut name = local.file_name()?.to_os_string();
+        name.push(".gz");
+        let gzip = local.with_file_name(name);
+        if !gzip.is_file() {
+            return None;
+        }
+        let canonical = fs::canonicalize(&gzip).ok()?;
+        if !canonical.starts_with(fs::canonicalize(&self.root).ok()?) {
+            return None;
+        }
+        Some(gzip)
+    }
+
+    fn list_dir(&self, url_path: &str, local: &Path) -> ResponseResult {
+        let entries = match fs::read_dir(local) {
+            Ok(v) => v,
+            Err(e) => return Err(HttpError::from(format!("Fail to list {}: {e}", local.display()))),
+        };
+        let root = fs::canonicalize(&self.root).unwrap_or_else(|_| self.root.clone());
+        let mut names: Vec<(bool, String)> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().into_string().ok()?;
+                if !self.hidden && name.starts_with('.') {
+                    return None;
+                }
+                // not served, see `resolve`
+                if !fs::canonicalize(entry.path()).ok()?.starts_with(&root) {
+                    return None;
+                }
+                Some((entry.path().is_dir(), name))
+            })
+            .collect();
+        // directories first
+        names.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+        let title = escape_html(&percent_decode(url_path).unwrap_or_else(|| String::from(url_path)));
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {title}</title></head>\n<body>\n<h1>Index of {title}</h1>\n<ul>\n"
+        );
+        if url_path != "/" {
+            html.push_str("<li><a href=\"../\">../</a></li>\n");
+        }
+        for (is_dir, name) in names {
+            let slash = if is_dir { "/" } else { "" };
+            html.push_str(&format!(
+                "<li><a href=\"{}{slash}\">{}{slash}</a></li>\n",
+                percent_encode(&name),
+                escape_html(&name),
+            ));
+        }
+        html.push_str("</ul>\n</body>\n</html>\n");
+        Ok(Box::new(Response::new(200, Default::default(), MakeHtmlContent(html))?))
+    }
+}
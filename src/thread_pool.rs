@@ -1,7 +1,184 @@
-use std::sync::{mpsc, Arc, Mutex};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 
 
-pub type Job = Box<dyn FnOnce() + Send + 'static>;
+/// Whether a job is executed by a worker or rejected because the queue is full
+pub enum JobStatus {
+    Run,
+    Rejected,
+}
+
+pub type Job = Box<dyn FnOnce(JobStatus) + Send + 'static>;
+
+
+/// What to do with a new job when the queue is full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QueuePolicy {
+    /// wait until a worker takes a job, so the caller stops accepting
+    Block,
+    /// reject the new job
+    Reject,
+    /// reject the oldest queued job to make room for the new one
+    DropOldest,
+}
+
+impl std::str::FromStr for QueuePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(QueuePolicy::Block),
+            "reject" => Ok(QueuePolicy::Reject),
+            "drop-oldest" => Ok(QueuePolicy::DropOldest),
+            _ => Err(format!("Unknown queue policy {s}, expect block, reject or drop-oldest")),
+        }
+    }
+}
+
+
+/// Counters of the job queue, readable while the pool is running
+pub struct QueueMetrics {
+    capacity: Option<usize>,
+    depth: AtomicUsize,
+    max_depth: AtomicUsize,
+    submitted: AtomicUsize,
+    rejected: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+impl QueueMetrics {
+    /// None if the queue is unbounded
+    pub fn capacity(&self) -> Option<usize> { self.capacity }
+    /// jobs waiting for a worker
+    pub fn depth(&self) -> usize { self.depth.load(Ordering::Relaxed) }
+    /// the highest depth seen
+    pub fn max_depth(&self) -> usize { self.max_depth.load(Ordering::Relaxed) }
+    pub fn submitted(&self) -> usize { self.submitted.load(Ordering::Relaxed) }
+    /// new jobs rejected with `QueuePolicy::Reject`
+    pub fn rejected(&self) -> usize { self.rejected.load(Ordering::Relaxed) }
+    /// queued jobs rejected with `QueuePolicy::DropOldest`
+    pub fn dropped(&self) -> usize { self.dropped.load(Ordering::Relaxed) }
+
+    fn set_depth(&self, depth: usize) {
+        self.depth.store(depth, Ordering::Relaxed);
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+}
+
+impl std::fmt::Display for QueueMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let capacity = match self.capacity {
+            Some(v) => v.to_string(),
+            None => String::from("unbounded"),
+        };
+        write!(f,
+            "queue(depth: {}/{capacity}, max depth: {}, submitted: {}, rejected: {}, dropped: {})",
+            self.depth(),
+            self.max_depth(),
+            self.submitted(),
+            self.rejected(),
+            self.dropped(),
+        )
+    }
+}
+
+
+/// Readable message of a caught panic
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+
+struct QueueState {
+    jobs: VecDeque<Job>,
+    closed: bool,
+}
+
+struct JobQueue {
+    state: Mutex<QueueState>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    policy: QueuePolicy,
+    metrics: Arc<QueueMetrics>,
+}
+
+impl JobQueue {
+    fn is_full(&self, state: &QueueState) -> bool {
+        match self.metrics.capacity {
+            Some(capacity) => state.jobs.len() >= capacity,
+            None => false,
+        }
+    }
+
+    /// A panicking job never holds the lock, but don't let a poisoned lock
+    /// take down every worker either
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait for the next job, None if the pool is shutting down
+    fn pop(&self) -> Option<Job> {
+        let mut state = self.lock();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                self.metrics.set_depth(state.jobs.len());
+                self.not_full.notify_one();
+                return Some(job);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.not_empty.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn push(&self, job: Job) {
+        self.metrics.submitted.fetch_add(1, Ordering::Relaxed);
+        let mut state = self.lock();
+        let mut rejected: Option<Job> = None;
+        if self.is_full(&state) {
+            match self.policy {
+                QueuePolicy::Block => {
+                    while self.is_full(&state) {
+                        state = self.not_full.wait(state).unwrap_or_else(|e| e.into_inner());
+                    }
+                },
+                QueuePolicy::Reject => {
+                    drop(state);
+                    self.metrics.rejected.fetch_add(1, Ordering::Relaxed);
+                    job(JobStatus::Rejected);
+                    return;
+                },
+                QueuePolicy::DropOldest => {
+                    rejected = state.jobs.pop_front();
+                    self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                },
+            }
+        }
+        state.jobs.push_back(job);
+        self.metrics.set_depth(state.jobs.len());
+        self.not_empty.notify_one();
+        drop(state);
+        // run the rejection outside of the lock
+        if let Some(job) = rejected {
+            job(JobStatus::Rejected);
+        }
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.not_empty.notify_all();
+    }
+}
 
 
 pub struct Worker {
@@ -10,15 +187,21 @@ pub struct Worker {
 }
 
 impl Worker {
-    pub fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Self {
+    fn new(id: usize, queue: Arc<JobQueue>) -> Self {
         let thread = std::thread::spawn(move || loop {
-            match receiver.lock().unwrap().recv() {
-                Ok(job) => {
+            match queue.pop() {
+                Some(job) => {
                     println!("Worker {id} got a job; executing.");
-                    job();
-                    println!("Worker {id} finish a job.");
+                    // a panicking job must not shrink the pool
+                    match catch_unwind(AssertUnwindSafe(|| job(JobStatus::Run))) {
+                        Ok(_) => println!("Worker {id} finish a job."),
+                        Err(e) => println!(
+                            "Worker {id} recovered from a panicking job: {}",
+                            panic_message(&*e),
+                        ),
+                    }
                 },
-                Err(_) => {
+                None => {
                     println!("Worker {id} disconnected; shutting down.");
                     break;
                 }
@@ -32,34 +215,71 @@ impl Worker {
 
 pub struct ThreadPool {
     workers: Vec<Worker>,
-    sender: Option<mpsc::Sender<Job>>,
+    queue: Arc<JobQueue>,
 }
 
 impl ThreadPool {
+    /// Pool with an unbounded job queue
     pub fn new(size: usize) -> Self {
+        Self::with_queue(size, None, QueuePolicy::Block)
+    }
+
+    /// Pool whose queue holds at most `capacity` waiting jobs, `policy`
+    /// decides what happens to a job submitted when the queue is full.
+    pub fn with_queue(size: usize, capacity: Option<usize>, policy: QueuePolicy) -> Self {
         assert!(size > 0);
+        assert!(capacity != Some(0));
         let mut workers = Vec::with_capacity(size);
-        let (sender, receiver) = mpsc::channel();
-        let receiver = Arc::new(Mutex::new(receiver));
+        let queue = Arc::new(JobQueue {
+            state: Mutex::new(QueueState { jobs: VecDeque::new(), closed: false }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            policy,
+            metrics: Arc::new(QueueMetrics {
+                capacity,
+                depth: AtomicUsize::new(0),
+                max_depth: AtomicUsize::new(0),
+                submitted: AtomicUsize::new(0),
+                rejected: AtomicUsize::new(0),
+                dropped: AtomicUsize::new(0),
+            }),
+        });
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&queue)));
         }
-        ThreadPool { workers, sender: Some(sender) }
+        ThreadPool { workers, queue }
     }
-    
+
     pub fn execute<F>(&self, f: F)
-    where 
+    where
         F: FnOnce() + Send + 'static,
     {
-        let job = Box::new(f);
-        self.sender.as_ref().unwrap().send(job).unwrap();
+        self.submit(move |status| {
+            if let JobStatus::Run = status {
+                f();
+            }
+        });
+    }
+
+    /// Queue a job that is told whether it runs or is rejected by the
+    /// queue policy, so it can release its resources, e.g. answer a
+    /// connection with 503.
+    pub fn submit<F>(&self, f: F)
+    where
+        F: FnOnce(JobStatus) + Send + 'static,
+    {
+        self.queue.push(Box::new(f));
+    }
+
+    pub fn metrics(&self) -> Arc<QueueMetrics> {
+        Arc::clone(&self.queue.metrics)
     }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        drop(self.sender.take());
+        self.queue.close();
 
         for worker in &mut self.workers {
             println!("Shutting down worker {}", worker.id);
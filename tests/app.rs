@@ -0,0 +1,52 @@
+//! Responses of `App::handle_request` to requests which cannot be read are
+//! rendered with the error handlers of the app, like the other errors.
+use std::io::{BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+
+use webserver::app::App;
+use webserver::request::BodyLimits;
+use webserver::response::make_text_response;
+
+
+/// Raw response of `app` to `raw`
+fn respond(app: &App, raw: &str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    client.write_all(raw.as_bytes()).unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    app.handle_request(&mut BufReader::new(&stream), &stream, 1).unwrap();
+    drop(stream);
+    let mut resp = String::new();
+    client.read_to_string(&mut resp).unwrap();
+    resp
+}
+
+
+#[test]
+fn parse_errors() {
+    let mut app = App::new();
+    app.set_body_limits(BodyLimits { max_size: 16, ..BodyLimits::new() });
+    for status in [400, 413, 501] {
+        app.set_error_handler(status, move |request, error| {
+            let page = format!("page {status} of {:?}: {}", request.path, error.message);
+            Box::new(make_text_response(status, page).unwrap())
+        });
+    }
+    let cases = [
+        // request, status line, page
+        ("NOT A REQUEST\r\n\r\n", "HTTP/1.1 400", "page 400 of \"\""),
+        ("POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n", "HTTP/1.1 400", "page 400 of \"\""),
+        ("POST / HTTP/1.1\r\nContent-Length: 17\r\n\r\n", "HTTP/1.1 413", "page 413 of \"\""),
+        ("POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n", "HTTP/1.1 501", "page 501 of \"\""),
+    ];
+    for (raw, status_line, page) in cases {
+        let resp = respond(&app, raw);
+        assert!(resp.starts_with(status_line), "{raw:?}: {resp}");
+        assert!(resp.contains(page), "{raw:?}: {resp}");
+    }
+    // the default JSON body without an error handler
+    let resp = respond(&App::new(), "NOT A REQUEST\r\n\r\n");
+    assert!(resp.starts_with("HTTP/1.1 400") && resp.contains("\"detail\""), "{resp}");
+}
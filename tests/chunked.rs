@@ -0,0 +1,136 @@
+//! Request bodies with "Transfer-Encoding: chunked", RFC 9112 section 7.1:
+//! chunk sizes and extensions, trailers, the body size limit, and the
+//! bounds on chunk lines and on the trailer section.
+use std::collections::HashMap;
+use std::io::{BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+
+use webserver::error::HttpError;
+use webserver::request::content_type::RawDataType;
+use webserver::request::{BodyLimits, Request};
+
+
+const LIMIT: usize = 64;
+
+/// Parse the raw bytes of a request sent over a loopback connection
+fn read(raw: &[u8]) -> Result<(String, HashMap<String, String>), HttpError> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    client.write_all(raw).unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    let limits = BodyLimits { max_size: LIMIT, ..BodyLimits::new() };
+    let request = Request::from_reader(&mut BufReader::new(&stream), &limits)?;
+    let body = match request.body.as_ref().map(|v| v.content()) {
+        Some(RawDataType::Text(text)) => text.clone(),
+        _ => String::new(),
+    };
+    Ok((body, request.trailers))
+}
+
+fn chunked(body: &str) -> Vec<u8> {
+    with_encoding("chunked", body)
+}
+
+fn with_encoding(transfer_encoding: &str, body: &str) -> Vec<u8> {
+    format!(
+        "POST / HTTP/1.1\r\nContent-Type: text/plain\r\nTransfer-Encoding: {transfer_encoding}\r\n\r\n{body}"
+    ).into_bytes()
+}
+
+
+#[test]
+fn decoded() {
+    let cases = [
+        // chunked body, decoded body
+        ("5\r\nhello\r\n0\r\n\r\n", "hello"),
+        ("5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n", "hello world"),
+        ("0\r\n\r\n", ""),
+        ("00000\r\n\r\n", ""),
+        // sizes are hexadecimal
+        ("a\r\n0123456789\r\n0\r\n\r\n", "0123456789"),
+        ("A\r\n0123456789\r\n0\r\n\r\n", "0123456789"),
+        // extensions are ignored
+        ("5;name=value\r\nhello\r\n0;last\r\n\r\n", "hello"),
+        ("5 ; name=\"a;b\"\r\nhello\r\n0\r\n\r\n", "hello"),
+        // exactly the limit
+        (&format!("40\r\n{}\r\n0\r\n\r\n", "a".repeat(LIMIT)), &"a".repeat(LIMIT)),
+    ];
+    for (body, expected) in cases {
+        match read(&chunked(body)) {
+            Ok((decoded, _)) => assert_eq!(decoded, expected, "{body:?}"),
+            Err(e) => panic!("{body:?} gave {} {}", e.status, e.message),
+        }
+    }
+    // the coding is case-insensitive
+    assert_eq!(read(&with_encoding("Chunked", "2\r\nok\r\n0\r\n\r\n")).unwrap().0, "ok");
+}
+
+#[test]
+fn trailers() {
+    let (body, trailers) = read(&chunked("2\r\nok\r\n0\r\nX-Checksum: abc\r\nX-Count: 2\r\n\r\n")).unwrap();
+    assert_eq!(body, "ok");
+    assert_eq!(trailers.get("X-Checksum").map(|v| v.as_str()), Some("abc"));
+    assert_eq!(trailers.get("X-Count").map(|v| v.as_str()), Some("2"));
+}
+
+#[test]
+fn invalid() {
+    let long_ext = format!("5;{}\r\nhello\r\n0\r\n\r\n", "x".repeat(5000));
+    let no_newline = "1".repeat(10_000);
+    let large_trailers = format!("0\r\n{}\r\n", "X-Pad: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n".repeat(200));
+    let long_trailer = format!("0\r\nX-Pad: {}\r\n\r\n", "a".repeat(10_000));
+    let cases = [
+        // chunked body, status
+        ("", 400),
+        ("5\r\nhel", 400),
+        ("5\r\nhello", 400),
+        ("5\r\nhelloXX0\r\n\r\n", 400),
+        ("5\nhello\r\n0\r\n\r\n", 400),
+        ("x\r\nhello\r\n0\r\n\r\n", 400),
+        ("-5\r\nhello\r\n0\r\n\r\n", 400),
+        ("\r\nhello\r\n0\r\n\r\n", 400),
+        ("fffffffffffffffffffff\r\n", 400),
+        ("5\r\nhello\r\n0\r\n", 400),
+        ("0\r\nnot a field\r\n\r\n", 400),
+        // over the body size limit
+        ("41\r\n", 413),
+        ("ffffffffffffffff\r\n", 413),
+        (&format!("20\r\n{0}\r\n20\r\n{0}\r\n1\r\na\r\n0\r\n\r\n", "a".repeat(32)), 413),
+        // lines are bounded
+        (&long_ext, 400),
+        (&no_newline, 400),
+        (&large_trailers, 431),
+        (&long_trailer, 431),
+    ];
+    for (body, status) in cases {
+        match read(&chunked(body)) {
+            Ok((decoded, _)) => panic!("{body:.40?} decoded {decoded:?}"),
+            Err(e) => assert_eq!(e.status, status, "{body:.40?}: {}", e.message),
+        }
+    }
+}
+
+#[test]
+fn transfer_codings() {
+    let body = "2\r\nok\r\n0\r\n\r\n";
+    let cases = [
+        // Transfer-Encoding, status
+        ("gzip, chunked", 501),
+        ("chunked, gzip", 501),
+        ("gzip", 501),
+        ("identity, chunked", 501),
+        ("chunked, chunked", 400),
+        ("", 400),
+    ];
+    for (transfer_encoding, status) in cases {
+        match read(&with_encoding(transfer_encoding, body)) {
+            Ok((decoded, _)) => panic!("{transfer_encoding:?} decoded {decoded:?}"),
+            Err(e) => assert_eq!(e.status, status, "{transfer_encoding:?}: {}", e.message),
+        }
+    }
+    // never both framings
+    let raw = "POST / HTTP/1.1\r\nContent-Length: 2\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nok\r\n0\r\n\r\n";
+    assert_eq!(read(raw.as_bytes()).unwrap_err().status, 400);
+}
@@ -0,0 +1,143 @@
+//! `negotiate_encoding` against the q-values of RFC 9110 section 12.5.3,
+//! with `*` and `identity`, and `decode_body` undoing the codings of a
+//! request body, RFC 9110 section 8.4.
+use std::io::Write;
+
+use flate2::Compression as Level;
+use flate2::write::DeflateEncoder;
+use webserver::compression::{Encoding, compress, decode_body, negotiate_encoding};
+
+
+const LIMIT: usize = 1024;
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    compress(bytes, Encoding::Gzip, 6).unwrap()
+}
+
+fn zlib(bytes: &[u8]) -> Vec<u8> {
+    compress(bytes, Encoding::Deflate, 6).unwrap()
+}
+
+/// deflate data without the zlib wrapper
+fn raw_deflate(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(vec![], Level::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+
+#[test]
+fn negotiate() {
+    use Encoding::*;
+    let cases = [
+        // value, expected
+        ("", None),
+        ("gzip", Some(Gzip)),
+        ("deflate", Some(Deflate)),
+        ("GZIP", Some(Gzip)),
+        ("x-gzip", Some(Gzip)),
+        // the first available between equal q-values
+        ("gzip, deflate", Some(Gzip)),
+        ("deflate, gzip", Some(Gzip)),
+        ("gzip;q=0.5, deflate", Some(Deflate)),
+        ("gzip; q=0.5, deflate; q=0.8", Some(Deflate)),
+        // q=0 is not acceptable
+        ("gzip;q=0", None),
+        ("gzip;q=0, deflate;q=0", None),
+        ("gzip;q=0, deflate", Some(Deflate)),
+        // unknown codings only
+        ("br", None),
+        // `*` is the q-value of the codings not listed
+        ("*", Some(Gzip)),
+        ("*;q=0", None),
+        ("gzip;q=0, *", Some(Deflate)),
+        ("*;q=0.5, deflate", Some(Deflate)),
+        // the identity wins over lower q-values only
+        ("identity", None),
+        ("gzip, identity", Some(Gzip)),
+        ("gzip;q=0.5, identity", None),
+        ("gzip;q=0.5, identity;q=0.2", Some(Gzip)),
+        ("gzip;q=0.5, *;q=0.8", Some(Deflate)),
+        ("gzip, identity;q=0", Some(Gzip)),
+        // invalid q-values are 0, others are clamped
+        ("gzip;q=abc, deflate;q=0.1", Some(Deflate)),
+        ("gzip;q=-1", None),
+        ("gzip;q=5, deflate", Some(Gzip)),
+    ];
+    for (value, expected) in cases {
+        assert_eq!(negotiate_encoding(value, &[Gzip, Deflate]), expected, "{value:?}");
+    }
+}
+
+#[test]
+fn negotiate_available() {
+    use Encoding::*;
+    let cases = [
+        // value, available, expected
+        ("gzip, deflate", &[Deflate, Gzip][..], Some(Deflate)),
+        ("gzip", &[Deflate], None),
+        ("*", &[Deflate], Some(Deflate)),
+        ("gzip", &[], None),
+    ];
+    for (value, available, expected) in cases {
+        assert_eq!(negotiate_encoding(value, available), expected, "{value:?} of {available:?}");
+    }
+}
+
+#[test]
+fn decode() {
+    let text = b"hello, hello, hello, hello";
+    let cases = [
+        // body, Content-Encoding
+        (text.to_vec(), ""),
+        (text.to_vec(), "identity"),
+        (gzip(text), "gzip"),
+        (gzip(text), "x-gzip"),
+        (gzip(text), "GZip"),
+        (zlib(text), "deflate"),
+        (raw_deflate(text), "deflate"),
+        (gzip(text), "identity, gzip"),
+        // undone from the last coding applied
+        (zlib(&gzip(text)), "gzip, deflate"),
+        (gzip(&gzip(text)), "gzip,gzip"),
+        (gzip(&raw_deflate(text)), "deflate, , gzip"),
+        // concatenated gzip members
+        ([gzip(b"hello, "), gzip(b"hello, hello, hello")].concat(), "gzip"),
+    ];
+    for (body, coding) in cases {
+        match decode_body(body, coding, LIMIT) {
+            Ok(decoded) => assert_eq!(decoded, text, "{coding:?}"),
+            Err(e) => panic!("{coding:?} gave {} {}", e.status, e.message),
+        }
+    }
+}
+
+#[test]
+fn decode_errors() {
+    let text = b"hello";
+    let large = vec![b'a'; LIMIT + 1];
+    let cases = [
+        // body, Content-Encoding, status
+        (text.to_vec(), "br", 415),
+        (gzip(text), "gzip, br", 415),
+        (text.to_vec(), "gzip", 400),
+        (text.to_vec(), "deflate", 400),
+        (gzip(text)[..10].to_vec(), "gzip", 400),
+        (gzip(&large), "gzip", 413),
+        (zlib(&large), "deflate", 413),
+        (raw_deflate(&large), "deflate", 413),
+        // the limit is checked after each coding
+        (gzip(&gzip(&large)), "gzip, gzip", 413),
+    ];
+    for (body, coding, status) in cases {
+        match decode_body(body, coding, LIMIT) {
+            Ok(decoded) => panic!("{coding:?} decoded {} bytes", decoded.len()),
+            Err(e) => assert_eq!(e.status, status, "{coding:?}: {}", e.message),
+        }
+    }
+    // exactly the limit is accepted
+    assert_eq!(decode_body(gzip(&large[1..]), "gzip", LIMIT).unwrap().len(), LIMIT);
+
+    let e = decode_body(text.to_vec(), "br", LIMIT).unwrap_err();
+    assert_eq!(e.headers.get("Accept-Encoding").map(|(v, _)| v.as_str()), Some("gzip, deflate"));
+}
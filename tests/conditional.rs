@@ -0,0 +1,194 @@
+//! `evaluate_preconditions` in the order of RFC 9110 section 13.2.2:
+//! If-Match, then If-Unmodified-Since when there is no If-Match, then
+//! If-None-Match, then If-Modified-Since on GET and HEAD when there is no
+//! If-None-Match.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use webserver::conditional::{Precondition, evaluate_preconditions, if_range_matches};
+use webserver::extensions::Extensions;
+use webserver::http::{self, METHOD, PROTOCOL, format_http_date, parse_http_date};
+use webserver::request::Request;
+
+
+const ETAG: &str = "\"v2\"";
+
+fn modified() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+}
+
+/// HTTP-date `secs` seconds after the modification time
+fn date(secs: i64) -> String {
+    let time = if secs >= 0 {
+        modified() + Duration::from_secs(secs as u64)
+    } else {
+        modified() - Duration::from_secs(secs.unsigned_abs())
+    };
+    format_http_date(time)
+}
+
+fn request(method: http::Method<'static>, headers: &[(&str, String)]) -> Request<'static> {
+    Request {
+        protocol: PROTOCOL::HTTP_1_1,
+        method,
+        path: String::from("/"),
+        username: None,
+        password: None,
+        query: HashMap::new(),
+        fragment: None,
+        headers: headers.iter().map(|(k, v)| (k.to_string(), v.clone())).collect(),
+        body: None,
+        trailers: HashMap::new(),
+        state: Arc::new(Extensions::new()),
+        extensions: Extensions::new(),
+    }
+}
+
+
+#[test]
+fn safe_methods() {
+    use Precondition::*;
+    let s = String::from;
+    let cases = [
+        (vec![], Proceed),
+        // If-None-Match
+        (vec![("If-None-Match", s(ETAG))], NotModified),
+        (vec![("If-None-Match", s("W/\"v2\""))], NotModified),
+        (vec![("If-None-Match", s("\"v1\", \"v2\""))], NotModified),
+        (vec![("If-None-Match", s("*"))], NotModified),
+        (vec![("If-None-Match", s("\"v1\""))], Proceed),
+        // If-Modified-Since
+        (vec![("If-Modified-Since", date(0))], NotModified),
+        (vec![("If-Modified-Since", date(60))], NotModified),
+        (vec![("If-Modified-Since", date(-60))], Proceed),
+        (vec![("If-Modified-Since", s("not a date"))], Proceed),
+        // If-None-Match takes precedence over If-Modified-Since
+        (vec![("If-None-Match", s("\"v1\"")), ("If-Modified-Since", date(60))], Proceed),
+        (vec![("If-None-Match", s(ETAG)), ("If-Modified-Since", date(-60))], NotModified),
+        // If-Match is compared strongly
+        (vec![("If-Match", s(ETAG))], Proceed),
+        (vec![("If-Match", s("*"))], Proceed),
+        (vec![("If-Match", s("W/\"v2\""))], Failed),
+        (vec![("If-Match", s("\"v1\""))], Failed),
+        // If-Unmodified-Since
+        (vec![("If-Unmodified-Since", date(0))], Proceed),
+        (vec![("If-Unmodified-Since", date(-60))], Failed),
+        // If-Match takes precedence over If-Unmodified-Since
+        (vec![("If-Match", s(ETAG)), ("If-Unmodified-Since", date(-60))], Proceed),
+        // a failed If-Match wins over a matching If-None-Match
+        (vec![("If-Match", s("\"v1\"")), ("If-None-Match", s(ETAG))], Failed),
+    ];
+    for method in ["GET", "HEAD"] {
+        for (headers, expected) in &cases {
+            let request = request(http::Method(method), headers);
+            assert_eq!(
+                evaluate_preconditions(&request, Some(ETAG), Some(modified())),
+                *expected,
+                "{method} with {headers:?}",
+            );
+        }
+    }
+}
+
+#[test]
+fn unsafe_methods() {
+    use Precondition::*;
+    let s = String::from;
+    let cases = [
+        (vec![], Proceed),
+        (vec![("If-Match", s(ETAG))], Proceed),
+        (vec![("If-Match", s("\"v1\""))], Failed),
+        // a matching If-None-Match fails instead of 304
+        (vec![("If-None-Match", s(ETAG))], Failed),
+        (vec![("If-None-Match", s("*"))], Failed),
+        (vec![("If-None-Match", s("\"v1\""))], Proceed),
+        // If-Modified-Since is only for GET and HEAD
+        (vec![("If-Modified-Since", date(60))], Proceed),
+        (vec![("If-Unmodified-Since", date(-60))], Failed),
+    ];
+    for method in ["POST", "PUT", "DELETE"] {
+        for (headers, expected) in &cases {
+            let request = request(http::Method(method), headers);
+            assert_eq!(
+                evaluate_preconditions(&request, Some(ETAG), Some(modified())),
+                *expected,
+                "{method} with {headers:?}",
+            );
+        }
+    }
+}
+
+#[test]
+fn missing_validators() {
+    use Precondition::*;
+    let s = String::from;
+    let cases = [
+        // no current representation to match
+        ("PUT", vec![("If-Match", s("*"))], Proceed),
+        ("PUT", vec![("If-Match", s(ETAG))], Failed),
+        ("PUT", vec![("If-None-Match", s("*"))], Failed),
+        ("GET", vec![("If-None-Match", s(ETAG))], Proceed),
+        // dates are ignored without a modification time
+        ("GET", vec![("If-Modified-Since", date(0))], Proceed),
+        ("PUT", vec![("If-Unmodified-Since", date(-60))], Proceed),
+    ];
+    for (method, headers, expected) in cases {
+        let request = request(http::Method(method), &headers);
+        assert_eq!(
+            evaluate_preconditions(&request, None, None),
+            expected,
+            "{method} with {headers:?}",
+        );
+    }
+}
+
+#[test]
+fn if_range() {
+    let s = String::from;
+    let cases = [
+        (vec![], true),
+        (vec![("If-Range", s(ETAG))], true),
+        (vec![("If-Range", s("\"v1\""))], false),
+        // weak tags never match
+        (vec![("If-Range", s("W/\"v2\""))], false),
+        (vec![("If-Range", date(0))], true),
+        (vec![("If-Range", date(60))], false),
+        (vec![("If-Range", s("not a date"))], false),
+    ];
+    for (headers, expected) in cases {
+        let request = request(METHOD::GET, &headers);
+        assert_eq!(
+            if_range_matches(&request, Some(ETAG), Some(modified())),
+            expected,
+            "{headers:?}",
+        );
+    }
+}
+
+#[test]
+fn out_of_range_dates() {
+    let dates = [
+        "Sun, 06 Nov 300000000000 08:49:37 GMT",
+        "Sun, 06 Nov 9223372036854775807 08:49:37 GMT",
+        "Sun, 06 Nov 10000 08:49:37 GMT",
+        "Sun Nov  6 08:49:37 300000000000",
+        "Sunday, 06-Nov-300000000000 08:49:37 GMT",
+        "Sun, 06 Nov 1969 08:49:37 GMT",
+    ];
+    for value in dates {
+        assert_eq!(parse_http_date(value), None, "{value}");
+        // the headers are ignored as invalid dates
+        for (method, name) in [("GET", "If-Modified-Since"), ("PUT", "If-Unmodified-Since")] {
+            let request = request(http::Method(method), &[(name, String::from(value))]);
+            assert_eq!(
+                evaluate_preconditions(&request, Some(ETAG), Some(modified())),
+                Precondition::Proceed,
+                "{name}: {value}",
+            );
+        }
+        let request = request(METHOD::GET, &[("If-Range", String::from(value))]);
+        assert!(!if_range_matches(&request, Some(ETAG), Some(modified())), "{value}");
+    }
+    assert!(parse_http_date("Fri, 31 Dec 9999 23:59:59 GMT").is_some());
+}
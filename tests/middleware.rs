@@ -0,0 +1,112 @@
+//! Router middlewares see the errors of their routes as responses: the
+//! errors are rendered, with the error pages of the app, before the after
+//! hooks run.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use webserver::app::App;
+use webserver::error::HttpError;
+use webserver::extensions::Extensions;
+use webserver::http::{METHOD, PROTOCOL};
+use webserver::middleware::Middleware;
+use webserver::request::Request;
+use webserver::response::{MakeResponse, make_text_response};
+use webserver::router::{ResponseResult, Router};
+
+
+/// Adds a header to every response on its way out
+struct Tag(&'static str);
+
+impl Middleware for Tag {
+    fn after(&self, _request: &Request, response: &mut Box<dyn MakeResponse>) {
+        response.headers_mut().insert(String::from(self.0), (String::from("1"), vec![]));
+    }
+}
+
+/// Answers the requests of "/api/admin/..." with 401
+struct Deny;
+
+impl Middleware for Deny {
+    fn before(&self, request: &mut Request) -> Option<ResponseResult> {
+        if request.path.starts_with("/api/admin/denied") {
+            return Some(Err(HttpError::new(401, "Unauthorized")));
+        }
+        None
+    }
+}
+
+fn request(path: &str) -> Request<'static> {
+    Request {
+        protocol: PROTOCOL::HTTP_1_1,
+        method: METHOD::GET,
+        path: String::from(path),
+        username: None,
+        password: None,
+        query: HashMap::new(),
+        fragment: None,
+        headers: HashMap::new(),
+        body: None,
+        trailers: HashMap::new(),
+        state: Arc::new(Extensions::new()),
+        extensions: Extensions::new(),
+    }
+}
+
+fn app() -> App<'static> {
+    let mut admin = Router::new();
+    admin.add_middleware(Deny);
+    admin.add_middleware(Tag("X-Admin"));
+    admin.get("/denied", |_| Ok(Box::new(make_text_response(200, String::from("ok"))?)));
+    admin.get("/teapot", |_| Err(HttpError::new(418, "I'm a teapot")));
+
+    let mut api = Router::new();
+    api.add_middleware(Tag("X-Api"));
+    api.get("/ok", |_| Ok(Box::new(make_text_response(200, String::from("ok"))?)));
+    api.get("/teapot", |_| Err(HttpError::new(418, "I'm a teapot")));
+    api.get("/invalid", |_| Err(HttpError::new(422, "Unprocessable Content")));
+    api.include_router("/admin", Box::new(admin));
+
+    let mut app = App::new();
+    app.include_router("/api", Box::new(api));
+    app.set_error_handler(418, |_request, error| {
+        Box::new(make_text_response(418, format!("<h1>{}</h1>", error.message)).unwrap())
+    });
+    app
+}
+
+
+#[test]
+fn after_hooks_on_errors() {
+    let app = app();
+    let cases = [
+        // path, status, headers set by the after hooks
+        ("/api/ok", 200, &["X-Api"][..]),
+        ("/api/teapot", 418, &["X-Api"]),
+        ("/api/invalid", 422, &["X-Api"]),
+        ("/api/admin/teapot", 418, &["X-Api", "X-Admin"]),
+        // answered by a before hook, only the outer hooks run
+        ("/api/admin/denied", 401, &["X-Api"]),
+    ];
+    for (path, status, tags) in cases {
+        let resp = match app.route(&mut request(path)) {
+            Some(Ok(resp)) => resp,
+            Some(Err(e)) => panic!("{path} gave the error {} {}", e.status, e.message),
+            None => panic!("{path} not found"),
+        };
+        assert_eq!(resp.status().code, status, "{path}");
+        for tag in ["X-Api", "X-Admin"] {
+            assert_eq!(resp.headers().contains_key(tag), tags.contains(&tag), "{tag} of {path}");
+        }
+    }
+}
+
+#[test]
+fn error_pages() {
+    let app = app();
+    let resp = app.route(&mut request("/api/teapot")).unwrap().unwrap();
+    assert_eq!(resp.headers().get("Content-Type").map(|(v, _)| v.as_str()), Some("text/plain"));
+
+    // without an error page, the default JSON body
+    let resp = app.route(&mut request("/api/invalid")).unwrap().unwrap();
+    assert_eq!(resp.headers().get("Content-Type").map(|(v, _)| v.as_str()), Some("application/json"));
+}
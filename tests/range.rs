@@ -0,0 +1,106 @@
+//! `parse_range` against the rules of RFC 9110 section 14.1.2: suffix
+//! ranges, clamping to the content, merging, and the values answered with
+//! the whole content instead.
+use webserver::http::{ByteRanges, MAX_RANGES, parse_range};
+
+
+fn partial(ranges: &[(u64, u64)]) -> ByteRanges {
+    ByteRanges::Partial(ranges.to_vec())
+}
+
+
+#[test]
+fn ranges() {
+    let cases = [
+        // value, length of the content, expected
+        ("bytes=0-499", 1000, partial(&[(0, 499)])),
+        ("bytes=500-", 1000, partial(&[(500, 999)])),
+        ("bytes=-500", 1000, partial(&[(500, 999)])),
+        ("bytes=0-0,-1", 1000, partial(&[(0, 0), (999, 999)])),
+        // the last byte is clamped to the content
+        ("bytes=900-2000", 1000, partial(&[(900, 999)])),
+        // a suffix longer than the content is the whole content
+        ("bytes=-2000", 1000, partial(&[(0, 999)])),
+        ("bytes=0-", 1, partial(&[(0, 0)])),
+        // unit, spaces and empty items
+        ("Bytes = 1-2 , , 4-5", 10, partial(&[(1, 2), (4, 5)])),
+    ];
+    for (value, len, expected) in cases {
+        assert_eq!(parse_range(value, len), expected, "{value} of {len} bytes");
+    }
+}
+
+#[test]
+fn merged() {
+    let cases = [
+        // overlapping
+        ("bytes=0-10,5-20", 100, partial(&[(0, 20)])),
+        // adjacent
+        ("bytes=0-9,10-19", 100, partial(&[(0, 19)])),
+        // sorted first
+        ("bytes=50-59,0-9", 100, partial(&[(0, 9), (50, 59)])),
+        // contained
+        ("bytes=0-99,10-20", 100, partial(&[(0, 99)])),
+        // a suffix overlapping a range
+        ("bytes=0-95,-10", 100, partial(&[(0, 99)])),
+        ("bytes=0-1,3-4", 100, partial(&[(0, 1), (3, 4)])),
+    ];
+    for (value, len, expected) in cases {
+        assert_eq!(parse_range(value, len), expected, "{value} of {len} bytes");
+    }
+}
+
+#[test]
+fn unsatisfiable() {
+    let cases = [
+        ("bytes=1000-", 1000),
+        ("bytes=1000-1999", 1000),
+        ("bytes=-0", 1000),
+        ("bytes=0-", 0),
+        ("bytes=-5", 0),
+        ("bytes=2000-3000,-0", 1000),
+    ];
+    for (value, len) in cases {
+        assert_eq!(parse_range(value, len), ByteRanges::Unsatisfiable, "{value} of {len} bytes");
+    }
+    // one satisfiable range is enough
+    assert_eq!(parse_range("bytes=2000-3000,0-0", 1000), partial(&[(0, 0)]));
+}
+
+#[test]
+fn ignored() {
+    let cases = [
+        "",
+        "bytes",
+        "bytes=",
+        "items=0-5",
+        "bytes=abc",
+        "bytes=5",
+        "bytes=-",
+        "bytes=5-1",
+        "bytes=x-5",
+        "bytes=0-x",
+        "bytes=-x",
+        "bytes=0-1,oops",
+    ];
+    for value in cases {
+        assert_eq!(parse_range(value, 1000), ByteRanges::Full, "{value:?}");
+    }
+}
+
+#[test]
+fn too_many_ranges() {
+    let ranges = |n: u64| (0..n).map(|i| format!("{}-{}", i * 10, i * 10)).collect::<Vec<_>>().join(",");
+
+    let value = format!("bytes={}", ranges(MAX_RANGES as u64));
+    match parse_range(&value, 1000) {
+        ByteRanges::Partial(v) => assert_eq!(v.len(), MAX_RANGES),
+        v => panic!("{value} gave {v:?}"),
+    }
+    let value = format!("bytes={}", ranges(MAX_RANGES as u64 + 1));
+    assert_eq!(parse_range(&value, 1000), ByteRanges::Full);
+
+    // counted once merged
+    let value = format!("bytes={}", vec!["0-5"; MAX_RANGES * 2].join(","));
+    assert_eq!(parse_range(&value, 1000), partial(&[(0, 5)]));
+}
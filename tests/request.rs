@@ -0,0 +1,66 @@
+//! Several requests read from one persistent connection, with the empty
+//! lines allowed before a request line, RFC 9112 section 2.2.
+use std::io::{BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+
+use webserver::request::content_type::RawDataType;
+use webserver::request::{BodyLimits, Request};
+
+
+/// Paths and bodies of the requests sent on one connection, until the
+/// first error
+fn read_all(raw: &str) -> (Vec<(String, String)>, Option<usize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    client.write_all(raw.as_bytes()).unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    let mut buf_reader = BufReader::new(&stream);
+    let mut requests = vec![];
+    loop {
+        match Request::from_reader(&mut buf_reader, &BodyLimits::new()) {
+            Ok(request) => {
+                let body = match request.body.as_ref().map(|v| v.content()) {
+                    Some(RawDataType::Text(text)) => text.clone(),
+                    _ => String::new(),
+                };
+                requests.push((request.path, body));
+            },
+            Err(e) => return (requests, Some(e.status)),
+        }
+    }
+}
+
+
+#[test]
+fn persistent_connection() {
+    let post = "POST /a HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\nok";
+    let get = "GET /b HTTP/1.1\r\n\r\n";
+    let expected = vec![
+        (String::from("/a"), String::from("ok")),
+        (String::from("/b"), String::new()),
+    ];
+    let cases = [
+        format!("{post}{get}"),
+        // a stray CRLF after a body
+        format!("{post}\r\n{get}"),
+        format!("{post}\r\n\r\n\r\n{get}"),
+        format!("\r\n{post}{get}"),
+    ];
+    for raw in cases {
+        let (requests, error) = read_all(&raw);
+        assert_eq!(requests, expected, "{raw:?}");
+        // the connection is closed after the last request
+        assert_eq!(error, Some(400), "{raw:?}");
+    }
+}
+
+#[test]
+fn empty_lines_only() {
+    for raw in ["", "\r\n", "\r\n\r\n"] {
+        let (requests, error) = read_all(raw);
+        assert!(requests.is_empty(), "{raw:?}");
+        assert_eq!(error, Some(400), "{raw:?}");
+    }
+}
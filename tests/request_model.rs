@@ -0,0 +1,82 @@
+//! Form fields of a `RequestModel` when the body is missing or is not a
+//! form: only the required fields are reported, the others take their
+//! default as when the form has no entry for them.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use webserver::extensions::Extensions;
+use webserver::http::{METHOD, PROTOCOL};
+use webserver::middleware::RequestModel;
+use webserver::request::Request;
+use webserver::request::content_type::{ContentType, FileCursor, FormContent, HasContent, TextContent};
+
+
+#[derive(RequestModel)]
+struct NewPost {
+    #[param(form, min_len = 1)]
+    title: String,
+    #[param(form, default = [])]
+    tags: Vec<String>,
+    #[param(form)]
+    attachment: Option<FileCursor>,
+}
+
+fn request(body: ContentType) -> Request<'static> {
+    Request {
+        protocol: PROTOCOL::HTTP_1_1,
+        method: METHOD::POST,
+        path: String::from("/posts"),
+        username: None,
+        password: None,
+        query: HashMap::new(),
+        fragment: None,
+        headers: HashMap::new(),
+        body,
+        trailers: HashMap::new(),
+        state: Arc::new(Extensions::new()),
+        extensions: Extensions::new(),
+    }
+}
+
+fn text(content_type: &str, content: &str) -> Box<dyn HasContent> {
+    Box::new(TextContent { content_type: content_type.to_string(), content: content.to_string() })
+}
+
+fn form(entries: &[(&str, &str)]) -> ContentType {
+    let mut content = HashMap::<String, Vec<Box<dyn HasContent>>>::new();
+    for (name, value) in entries {
+        content.entry(name.to_string()).or_default().push(text("text/plain", value));
+    }
+    Some(Box::new(FormContent { content_type: String::from("application/x-www-form-urlencoded"), content }))
+}
+
+
+#[test]
+fn not_a_form() {
+    let cases: [(&str, ContentType, &str); 3] = [
+        ("no body", None, "Missing field \"title\""),
+        ("JSON body", Some(text("application/json", "{\"title\": \"a\"}")), "Request body is not a form"),
+        ("empty form", form(&[]), "Missing field \"title\""),
+    ];
+    for (case, body, reason) in cases {
+        let errs = match NewPost::from_request(&request(body), &HashMap::new()) {
+            Ok(_) => panic!("{case} is valid"),
+            Err(errs) => errs,
+        };
+        let fields: Vec<_> = errs.iter().map(|e| e.field.as_deref()).collect();
+        assert_eq!(fields, [Some("title")], "{case}");
+        assert!(errs[0].reason.contains(reason), "{case}: {}", errs[0].reason);
+    }
+}
+
+#[test]
+fn defaults() {
+    let post = NewPost::from_request(&request(form(&[("title", "hello")])), &HashMap::new()).unwrap();
+    assert_eq!(post.title, "hello");
+    assert!(post.tags.is_empty());
+    assert!(post.attachment.is_none());
+
+    let body = form(&[("title", "hello"), ("tags", "a"), ("tags", "b")]);
+    let post = NewPost::from_request(&request(body), &HashMap::new()).unwrap();
+    assert_eq!(post.tags, ["a", "b"]);
+}
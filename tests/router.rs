@@ -0,0 +1,103 @@
+//! Matching priority of overlapping routes: static segments, then
+//! parameters with a converter, then plain parameters, then `path`
+//! parameters, backtracking out of a branch with no route for the rest of
+//! the path.
+use webserver::http::{Method, METHOD};
+use webserver::response::make_empty_response;
+use webserver::router::Router;
+
+
+/// The routes registered in order, or in reverse order, which must not
+/// change the priority
+fn router(reversed: bool) -> Router<'static> {
+    let mut router = Router::new();
+    let mut paths = [
+        "/users/me",
+        "/users/me/settings",
+        "/users/{id:int}",
+        "/users/{id:int}/posts",
+        "/users/{name}",
+        "/users/{name}/profile",
+        "/codes/{code:[A-Z]{3}}",
+        "/codes/{other}",
+        "/files/{name}.{ext}",
+        "/files/{rest:path}",
+        "/{path:path}",
+    ];
+    if reversed {
+        paths.reverse();
+    }
+    for path in paths {
+        router.get(path, |_| Ok(Box::new(make_empty_response(204)?)));
+    }
+    router.post("/users/{name}", |_| Ok(Box::new(make_empty_response(204)?)));
+    router
+}
+
+fn routers() -> [Router<'static>; 2] {
+    [router(false), router(true)]
+}
+
+
+#[test]
+fn priority() {
+    let cases = [
+        // path, matched route
+        ("/users/me", "/users/me"),
+        ("/users/42", "/users/{id:int}"),
+        ("/users/bob", "/users/{name}"),
+        ("/users/me/settings", "/users/me/settings"),
+        ("/users/42/posts", "/users/{id:int}/posts"),
+        ("/users/bob/profile", "/users/{name}/profile"),
+        ("/codes/ABC", "/codes/{code:[A-Z]{3}}"),
+        ("/codes/abc", "/codes/{other}"),
+        ("/codes/ABCD", "/codes/{other}"),
+        ("/files/a.txt", "/files/{name}.{ext}"),
+        ("/files/a", "/files/{rest:path}"),
+        ("/files/a/b.txt", "/files/{rest:path}"),
+        ("/other", "/{path:path}"),
+        ("/a/b/c", "/{path:path}"),
+    ];
+    for router in routers() {
+        for (path, expected) in cases {
+            assert_eq!(router.route_path(&METHOD::GET, path), Some(expected), "{path}");
+        }
+    }
+}
+
+#[test]
+fn backtracking() {
+    let cases = [
+        // method, path, matched route
+        // the static `me` has no `profile`, nor `me` an int
+        (METHOD::GET, "/users/me/profile", "/users/{name}/profile"),
+        // `{id:int}` has no `profile`
+        (METHOD::GET, "/users/42/profile", "/users/{name}/profile"),
+        // no parameter branch has `posts` for a name
+        (METHOD::GET, "/users/bob/posts", "/{path:path}"),
+        (METHOD::GET, "/users/me/settings/more", "/{path:path}"),
+        (METHOD::GET, "/users/42/posts/1", "/{path:path}"),
+        // the routes of other methods are skipped
+        (METHOD::POST, "/users/me", "/users/{name}"),
+        (METHOD::POST, "/users/42", "/users/{name}"),
+    ];
+    for router in routers() {
+        for (method, path, expected) in &cases {
+            assert_eq!(router.route_path(method, path), Some(*expected), "{method} {path}");
+        }
+    }
+}
+
+#[test]
+fn not_found() {
+    let cases: [(Method, &str); 3] = [
+        (METHOD::POST, "/users/me/settings"),
+        (METHOD::POST, "/other"),
+        (METHOD::DELETE, "/users/me"),
+    ];
+    for router in routers() {
+        for (method, path) in &cases {
+            assert_eq!(router.route_path(method, path), None, "{method} {path}");
+        }
+    }
+}
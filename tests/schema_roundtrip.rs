@@ -0,0 +1,210 @@
+//! `json_schema()` of the fields agrees with their `validate()`: a value
+//! sent as text, e.g. in a query, is accepted exactly when the JSON value
+//! is valid against the schema.
+//!
+//! Only values of the type of the field are checked, text like "5" is a
+//! valid `Text` but 5 is not a JSON string.
+use webserver::json::{self, JsonValidator, JsonValue, json};
+use webserver::request::content_type::RawDataType;
+use webserver::schema::{
+    AnyJson,
+    Array,
+    Bool,
+    FieldValidate,
+    Float,
+    HasDefault,
+    Integer,
+    Mapping,
+    Regex,
+    Text,
+};
+
+
+/// Text of a value as sent by a client, strings without their quotes
+fn raw(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(s) => s.clone(),
+        v => json::dump(v).unwrap(),
+    }
+}
+
+fn assert_agree<F: FieldValidate>(field: &F, values: &[JsonValue]) {
+    let schema = field.json_schema();
+    let validator = JsonValidator::new(schema.clone()).unwrap();
+    for value in values {
+        let text = raw(value);
+        let validated = field.validate(RawDataType::Text(&text)).is_ok();
+        assert_eq!(
+            validated,
+            validator.is_valid(value),
+            "{value} is {} by validate() but not by {schema}",
+            if validated { "accepted" } else { "rejected" },
+        );
+    }
+}
+
+
+#[test]
+fn integer_bounds() {
+    let mut field: Integer<i64> = HasDefault::new();
+    field.gt = Some(0);
+    field.le = Some(10);
+    assert_eq!(field.json_schema(), json!({
+        "type": "integer",
+        "exclusiveMinimum": 0,
+        "maximum": 10,
+    }));
+    assert_agree(&field, &[json!(-1), json!(0), json!(1), json!(10), json!(11), json!(2.5)]);
+
+    let mut field: Integer<i64> = HasDefault::new();
+    field.ge = Some(-5);
+    field.lt = Some(5);
+    assert_agree(&field, &[json!(-6), json!(-5), json!(4), json!(5)]);
+}
+
+#[test]
+fn integer_choice() {
+    let mut field: Integer<u32> = HasDefault::new();
+    field.choice = Some(vec![1, 2, 4]);
+    field.common.default = Some(2);
+    assert_eq!(field.json_schema(), json!({"type": "integer", "enum": [1, 2, 4], "default": 2}));
+    assert_agree(&field, &[json!(1), json!(3), json!(4)]);
+}
+
+#[test]
+fn float_bounds() {
+    let mut field: Float<f64> = HasDefault::new();
+    field.ge = Some(0.5);
+    field.lt = Some(1.5);
+    assert_eq!(field.json_schema(), json!({
+        "type": "number",
+        "minimum": 0.5,
+        "exclusiveMaximum": 1.5,
+    }));
+    assert_agree(&field, &[json!(0.4), json!(0.5), json!(1), json!(1.49), json!(1.5)]);
+
+    let mut field: Float<f64> = HasDefault::new();
+    field.gt = Some(-1.0);
+    field.le = Some(1.0);
+    assert_agree(&field, &[json!(-1.0), json!(-0.99), json!(1.0), json!(1.01)]);
+}
+
+#[test]
+fn text_length() {
+    let mut field: Text = HasDefault::new();
+    field.min_len = Some(2);
+    field.max_len = Some(4);
+    assert_eq!(field.json_schema(), json!({"type": "string", "minLength": 2, "maxLength": 4}));
+    // lengths are in characters, "héé" is 3 characters but 5 bytes
+    assert_agree(&field, &[
+        json!(""),
+        json!("a"),
+        json!("ab"),
+        json!("abcd"),
+        json!("abcde"),
+        json!("héé"),
+        json!("日本語です"),
+    ]);
+}
+
+#[test]
+fn text_pattern_and_choice() {
+    let mut field: Text = HasDefault::new();
+    field.pattern = Some(Regex::new("^[a-z]+$").unwrap());
+    assert_eq!(field.json_schema(), json!({"type": "string", "pattern": "^[a-z]+$"}));
+    assert_agree(&field, &[json!("abc"), json!("aBc"), json!("abc1"), json!("")]);
+
+    // like JSON schema, a pattern matches anywhere in the value
+    let mut field: Text = HasDefault::new();
+    field.pattern = Some(Regex::new("[0-9]").unwrap());
+    assert_agree(&field, &[json!("a1b"), json!("ab")]);
+
+    let mut field: Text = HasDefault::new();
+    field.choice = Some(vec![String::from("name"), String::from("date")]);
+    field.common.default = Some(String::from("name"));
+    assert_eq!(field.json_schema(), json!({
+        "type": "string",
+        "enum": ["name", "date"],
+        "default": "name",
+    }));
+    assert_agree(&field, &[json!("name"), json!("date"), json!("size"), json!("Name")]);
+}
+
+#[test]
+fn bool() {
+    let field: Bool = HasDefault::new();
+    assert_eq!(field.json_schema(), json!({"type": "boolean"}));
+    assert_agree(&field, &[json!(true), json!(false)]);
+}
+
+#[test]
+fn array() {
+    let mut field: Array<Integer<i64>> = HasDefault::new();
+    field.min_len = Some(1);
+    field.max_len = Some(3);
+    field.elem_field.ge = Some(0);
+    assert_eq!(field.json_schema(), json!({
+        "type": "array",
+        "items": {"type": "integer", "minimum": 0},
+        "minItems": 1,
+        "maxItems": 3,
+    }));
+    assert_agree(&field, &[
+        json!([]),
+        json!([0]),
+        json!([1, 2, 3]),
+        json!([1, 2, 3, 4]),
+        json!([1, -1]),
+        json!([1, 2.5]),
+    ]);
+
+    let mut field: Array<Text> = HasDefault::new();
+    field.elem_field.choice = Some(vec![String::from("a"), String::from("b")]);
+    assert_agree(&field, &[json!(["a", "b", "a"]), json!(["a", "c"])]);
+}
+
+#[test]
+fn mapping() {
+    let mut field: Mapping<Integer<i64>> = HasDefault::new();
+    field.min_len = Some(1);
+    field.max_len = Some(2);
+    field.elem_field.lt = Some(100);
+    assert_eq!(field.json_schema(), json!({
+        "type": "object",
+        "additionalProperties": {"type": "integer", "exclusiveMaximum": 100},
+        "minProperties": 1,
+        "maxProperties": 2,
+    }));
+    assert_agree(&field, &[
+        json!({}),
+        json!({"a": 1}),
+        json!({"a": 1, "b": 99}),
+        json!({"a": 1, "b": 2, "c": 3}),
+        json!({"a": 100}),
+    ]);
+}
+
+#[test]
+fn nested() {
+    let mut field: Mapping<Array<Text>> = HasDefault::new();
+    field.elem_field.max_len = Some(2);
+    field.elem_field.elem_field.min_len = Some(1);
+    assert_agree(&field, &[
+        json!({"tags": ["a", "b"]}),
+        json!({"tags": ["a", "b", "c"]}),
+        json!({"tags": ["a", ""]}),
+    ]);
+}
+
+#[test]
+fn any_json() {
+    let schema = json!({
+        "type": "object",
+        "required": ["id"],
+        "properties": {"id": {"type": "integer"}},
+    });
+    let mut field: AnyJson = HasDefault::new();
+    field.schema = Some(schema.clone());
+    assert_eq!(field.json_schema(), schema);
+    assert_agree(&field, &[json!({"id": 1}), json!({"id": "1"}), json!({}), json!([1])]);
+}
@@ -0,0 +1,107 @@
+//! `StaticFiles` only serves the files under its root: `..`, encoded
+//! separators, NUL bytes, absolute paths, dotfiles and symlinks leaving the
+//! root are not found.
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use webserver::extensions::Extensions;
+use webserver::http::{METHOD, PROTOCOL};
+use webserver::request::Request;
+use webserver::static_files::StaticFiles;
+
+
+/// base/
+///   secret.txt
+///   root/
+///     public.txt
+///     .env
+///     sub/index.html
+///     link -> ../secret.txt
+fn make_dirs() -> PathBuf {
+    let base = std::env::temp_dir().join(format!("webserver-static-files-{}", std::process::id()));
+    let root = base.join("root");
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::write(base.join("secret.txt"), "secret").unwrap();
+    fs::write(root.join("public.txt"), "public").unwrap();
+    fs::write(root.join(".env"), "secret").unwrap();
+    fs::write(root.join("sub").join("index.html"), "<p>index</p>").unwrap();
+    #[cfg(unix)]
+    {
+        let _ = std::os::unix::fs::symlink(base.join("secret.txt"), root.join("link"));
+    }
+    base
+}
+
+fn request(path: &str) -> Request<'static> {
+    Request {
+        protocol: PROTOCOL::HTTP_1_1,
+        method: METHOD::GET,
+        path: format!("/static/{path}"),
+        username: None,
+        password: None,
+        query: HashMap::new(),
+        fragment: None,
+        headers: HashMap::new(),
+        body: None,
+        trailers: HashMap::new(),
+        state: Arc::new(Extensions::new()),
+        extensions: Extensions::new(),
+    }
+}
+
+
+#[test]
+fn traversal() {
+    let base = make_dirs();
+    let files = StaticFiles::new(base.join("root").to_str().unwrap()).unwrap();
+    let cases = [
+        // path under the mount prefix, status
+        ("public.txt", 200),
+        ("./public.txt", 200),
+        ("sub//index.html", 200),
+        ("sub/", 200),
+        ("pub%6cic.txt", 200),
+        ("missing.txt", 404),
+        // parent directories
+        ("..", 404),
+        ("../secret.txt", 404),
+        ("sub/../../secret.txt", 404),
+        ("sub/../public.txt", 404),
+        ("%2e%2e/secret.txt", 404),
+        ("%2E%2E/secret.txt", 404),
+        // encoded separators
+        ("..%2fsecret.txt", 404),
+        ("sub%2f..%2f..%2fsecret.txt", 404),
+        ("..\\secret.txt", 404),
+        ("..%5csecret.txt", 404),
+        // NUL bytes
+        ("public.txt%00.html", 404),
+        ("%00", 404),
+        // absolute paths stay under the root
+        ("//public.txt", 200),
+        ("/etc/passwd", 404),
+        ("%2fetc%2fpasswd", 404),
+        // invalid encodings
+        ("%zz", 404),
+        ("%", 404),
+        ("%ff", 404),
+        // dotfiles
+        (".env", 404),
+        ("%2eenv", 404),
+    ];
+    for (path, status) in cases {
+        let got = match files.serve(&request(path), path) {
+            Ok(resp) => resp.status().code,
+            Err(e) => e.status,
+        };
+        assert_eq!(got, status, "{path:?}");
+    }
+    #[cfg(unix)]
+    {
+        let got = files.serve(&request("link"), "link").map(|resp| resp.status().code);
+        assert_eq!(got.map_err(|e| e.status), Err(404), "symlink out of the root");
+    }
+    fs::remove_dir_all(base).unwrap();
+}
@@ -0,0 +1,288 @@
+//! `#[derive(RequestModel)]` for `webserver::middleware::RequestModel`.
+//!
+//!   #[derive(RequestModel)]
+//!   struct ListItems {
+//!       #[param(path)]
+//!       user_id: u64,
+//!       #[param(query, ge = 1, le = 100, default = 20)]
+//!       limit: i64,
+//!       #[param(query, max_len = 32, pattern = "^[a-z]+$")]
+//!       tag: Option<String>,
+//!       #[param(query, alias = "sort-by", choice = ["name", "date"])]
+//!       sort_by: Option<String>,
+//!       #[param(header, min_len = 16)]
+//!       x_api_key: String,
+//!       #[param(cookie)]
+//!       session: Option<String>,
+//!   }
+//!
+//! `form` fields are entries of a multipart or urlencoded body.
+//! Header names are matched case-insensitively, '_' in the field name stands
+//! for '-'. Every field is validated with the `schema::fields` validator of its type,
+//! `Option` fields are not required, even in the body, a `pattern` is a
+//! string literal checked when the model is derived, and `from_request`
+//! returns the errors of all fields together. `parameters()` describes the
+//! fields for the OpenAPI document.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input,
+    Data,
+    DeriveInput,
+    Expr,
+    ExprLit,
+    Fields,
+    GenericArgument,
+    Ident,
+    Lit,
+    LitStr,
+    PathArguments,
+    Type,
+};
+
+
+#[proc_macro_derive(RequestModel, attributes(param))]
+pub fn derive_request_model(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(v) => v.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+
+/// Parsed `#[param(...)]` of a field
+struct Param {
+    location: Option<Ident>,
+    alias: Option<String>,
+    default: Option<Expr>,
+    pattern: Option<LitStr>,
+    /// other constraints, set as `field.<name> = Some(<value>)`
+    constraints: Vec<(Ident, Expr)>,
+}
+
+const LOCATIONS: [&str; 6] = ["path", "query", "header", "cookie", "form", "body"];
+const CONSTRAINTS: [&str; 9] = [
+    "gt", "ge", "lt", "le", "min_len", "max_len", "choice", "allowed_exts", "schema",
+];
+
+fn parse_param(field: &syn::Field) -> syn::Result<Param> {
+    let mut param = Param {
+        location: None,
+        alias: None,
+        default: None,
+        pattern: None,
+        constraints: vec![],
+    };
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("param")) {
+        attr.parse_nested_meta(|meta| {
+            let ident = match meta.path.get_ident() {
+                Some(v) => v.clone(),
+                None => return Err(meta.error("expect an identifier")),
+            };
+            let name = ident.to_string();
+            if LOCATIONS.contains(&name.as_str()) {
+                if param.location.is_some() {
+                    return Err(meta.error("location is given twice"));
+                }
+                param.location = Some(ident);
+            } else if name == "alias" {
+                let value: Expr = meta.value()?.parse()?;
+                match value {
+                    Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) => param.alias = Some(s.value()),
+                    _ => return Err(meta.error("alias should be a string")),
+                }
+            } else if name == "default" {
+                param.default = Some(meta.value()?.parse()?);
+            } else if name == "pattern" {
+                let pattern: LitStr = match meta.value()?.parse() {
+                    Ok(v) => v,
+                    Err(_) => return Err(meta.error("pattern should be a string")),
+                };
+                // a typo fails the build rather than every request
+                if let Err(e) = regex_syntax::Parser::new().parse(&pattern.value()) {
+                    return Err(syn::Error::new(pattern.span(), format!("invalid pattern: {e}")));
+                }
+                param.pattern = Some(pattern);
+            } else if CONSTRAINTS.contains(&name.as_str()) {
+                param.constraints.push((ident, meta.value()?.parse()?));
+            } else {
+                return Err(meta.error(format!(
+                    "unknown parameter `{name}`, expect a location ({}), alias, default, pattern or one of {}",
+                    LOCATIONS.join(", "),
+                    CONSTRAINTS.join(", "),
+                )));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(param)
+}
+
+/// String literals become `String`s, arrays are converted item by item
+fn convert_value(value: &Expr) -> TokenStream2 {
+    match value {
+        Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) => quote! { ::std::string::String::from(#s) },
+        Expr::Array(arr) => {
+            let items = arr.elems.iter().map(convert_value);
+            quote! { vec![#(#items),*] }
+        },
+        _ => quote! { #value },
+    }
+}
+
+/// The `T` of `Option<T>`
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let path = match ty {
+        Type::Path(v) if v.qself.is_none() => &v.path,
+        _ => return None,
+    };
+    let segment = path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `Vec<u8>` is read as raw bytes instead of a JSON array
+fn is_bytes(ty: &Type) -> bool {
+    let s = quote!(#ty).to_string().replace(' ', "");
+    s == "Vec<u8>"
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new(input.span(), "RequestModel needs named fields")),
+        },
+        _ => return Err(syn::Error::new(input.span(), "RequestModel can only be derived for structs")),
+    };
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut extracts = vec![];
+    let mut params = vec![];
+    let mut inits = vec![];
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        let param = parse_param(field)?;
+        let location = match &param.location {
+            Some(v) => v.to_string(),
+            None => return Err(syn::Error::new(
+                field.span(),
+                format!("missing location of `{ident}`, e.g. #[param(query)]"),
+            )),
+        };
+        let key = match &param.alias {
+            Some(v) => v.clone(),
+            // x_api_key is read from the "x-api-key" header
+            None if location == "header" => ident.to_string().replace('_', "-"),
+            None => ident.to_string(),
+        };
+        let location = match location.as_str() {
+            "path" => quote! { ::webserver::schema::Location::Path },
+            "query" => quote! { ::webserver::schema::Location::Query },
+            "header" => quote! { ::webserver::schema::Location::Header },
+            "cookie" => quote! { ::webserver::schema::Location::Cookie },
+            "form" => quote! { ::webserver::schema::Location::Form },
+            _ => quote! { ::webserver::schema::Location::Body },
+        };
+
+        let optional = option_inner(&field.ty);
+        let ty = optional.unwrap_or(&field.ty);
+        let validator = if is_bytes(ty) {
+            quote! { ::webserver::schema::Binary }
+        } else {
+            quote! { <#ty as ::webserver::schema::HasValidator>::Field }
+        };
+        let required = optional.is_none() && param.default.is_none();
+        let default = match &param.default {
+            Some(v) => {
+                let v = convert_value(v);
+                quote! { Some(#v) }
+            },
+            None => quote! { None },
+        };
+        let constraints = param.constraints.iter().map(|(k, v)| {
+            let v = convert_value(v);
+            quote_spanned! { k.span() => validator.#k = Some(#v); }
+        });
+        let pattern = param.pattern.as_ref().map(|pattern| quote_spanned! { pattern.span() =>
+            // compiled once for all requests
+            static PATTERN: ::std::sync::OnceLock<::webserver::schema::Regex> = ::std::sync::OnceLock::new();
+            validator.pattern = Some(
+                PATTERN.get_or_init(|| {
+                    ::webserver::schema::Regex::new(#pattern).expect("pattern checked by the derive")
+                }).clone()
+            );
+        });
+
+        let build = quote! {
+            let mut validator = <#validator as ::webserver::schema::HasDefault>::new();
+            validator.common = ::webserver::schema::Common {
+                default: #default,
+                required: #required,
+                location: #location,
+                field: Some(::std::string::String::from(#key)),
+                val: None,
+            };
+            #(#constraints)*
+            #pattern
+        };
+        params.push(quote! {
+            {
+                #build
+                ::webserver::openapi::Parameter::from_field(&validator)
+            }
+        });
+        extracts.push(quote! {
+            let #ident = {
+                #build
+                match ::webserver::middleware::parse_request(validator, request, path_args) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        errs.extend(e);
+                        None
+                    },
+                }
+            };
+        });
+        inits.push(if optional.is_some() {
+            quote! { #ident }
+        } else {
+            // required or with a default, so set when there is no error
+            quote! { #ident: #ident.unwrap() }
+        });
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::webserver::middleware::RequestModel for #name #ty_generics #where_clause {
+            fn from_request(
+                request: &::webserver::request::Request,
+                path_args: &::std::collections::HashMap<::std::string::String, ::std::string::String>,
+            ) -> ::webserver::schema::ValidationResult<Self> {
+                let mut errs = ::webserver::schema::ValidationErrors::new();
+                #(#extracts)*
+                if !errs.is_empty() {
+                    return Err(errs);
+                }
+                Ok(Self {
+                    #(#inits),*
+                })
+            }
+
+            fn parameters() -> ::std::vec::Vec<::webserver::openapi::Parameter> {
+                vec![#(#params),*]
+            }
+        }
+    })
+}